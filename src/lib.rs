@@ -0,0 +1,3799 @@
+#![allow(
+    clippy::needless_return,
+    clippy::redundant_pattern_matching,
+    clippy::ptr_arg
+)]
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Write,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+// failures callers may want to tell apart, wrapped in anyhow so
+// `err.downcast_ref::<TroveError>()` recovers them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TroveError {
+    DuplicateName(String),
+    DuplicatePath(PathBuf),
+    DestinationConflict { entry: String, path: PathBuf },
+    NotFound(String),
+    NoEntries(String),
+    PathDoesNotExist(PathBuf),
+    InStore { path: PathBuf, store: PathBuf },
+    AmbiguousCriteria,
+    InvalidName { name: String, reason: &'static str },
+}
+
+impl std::fmt::Display for TroveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TroveError::DuplicateName(name) => write!(f, "Entry {} already exists.", name),
+            TroveError::DuplicatePath(path) => write!(f, "{} already exists.", path.display()),
+            TroveError::DestinationConflict { entry, path } => write!(
+                f,
+                "{} already deploys to {}, pass --allow-conflict to add it anyway.",
+                entry,
+                path.display()
+            ),
+            TroveError::NotFound(name) => write!(f, "No entry found by {}.", name),
+            TroveError::NoEntries(category) => write!(f, "No entries found in {}.", category),
+            TroveError::PathDoesNotExist(path) => {
+                write!(f, "{} does not exist or isn't a directory.", path.display())
+            }
+            TroveError::InStore { path, store } => write!(
+                f,
+                "{} is already inside the store at {}.",
+                path.display(),
+                store.display()
+            ),
+            TroveError::AmbiguousCriteria => write!(f, "Please specify only one criteria."),
+            TroveError::InvalidName { name, reason } => {
+                write!(f, "{:?} can't be an entry name, it {}.", name, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TroveError {}
+
+impl TroveError {
+    pub fn exit_code(&self) -> i32 {
+        // what the CLI exits with, so scripts can tell these cases apart
+        match self {
+            TroveError::DuplicateName(_)
+            | TroveError::DuplicatePath(_)
+            | TroveError::DestinationConflict { .. }
+            | TroveError::InStore { .. } => return 3,
+            TroveError::NotFound(_)
+            | TroveError::NoEntries(_)
+            | TroveError::PathDoesNotExist(_) => return 4,
+            TroveError::AmbiguousCriteria | TroveError::InvalidName { .. } => return 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub host_path: String,
+    pub categories: Vec<String>,
+    // directories added with ignore patterns are copied into the store instead of moved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+    // unix permission bits at add time, reapplied when a copy is deployed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    // why the file is tracked, free text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    // RFC 3339 UTC time the entry was added, identity stays the name alone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub added_at: Option<String>,
+    // host name of the machine the entry was added on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    // stored as age ciphertext, deployed by decrypting to the host path
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    // file name in the store when it differs from the entry name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store_name: Option<String>,
+    // hash of what a copied or encrypted entry wrote to the host path, for check-drift
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    // shell command deploy runs once this entry's link is created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_link: Option<String>,
+    // moved back to the host path by pack --restore, the next deploy takes it in again
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub externalized: bool,
+    // synthesized from the store on load, never written to trove.conf
+    #[serde(skip)]
+    pub discovered: bool,
+}
+
+impl Entry {
+    pub fn is_copy(&self) -> bool {
+        return !self.ignore.is_empty();
+    }
+
+    pub fn stored_name(&self) -> &str {
+        return self.store_name.as_deref().unwrap_or(&self.name);
+    }
+
+    pub fn is_linked(&self) -> bool {
+        // copies and encrypted entries are written to the host path instead
+        return !self.is_copy() && !self.encrypted;
+    }
+}
+
+// entries are identified by name alone, so the set never holds two with the same name
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        return self.name == other.name;
+    }
+}
+
+impl Eq for Entry {}
+
+impl std::hash::Hash for Entry {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TroveConfig {
+    // format of trove.conf, older files are migrated on load
+    #[serde(default)]
+    pub version: u32,
+    pub path: String,
+    pub store_path: String,
+    // glob patterns skipped when adding a directory
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    // match entry names regardless of case, which also makes nvim and NVIM collide
+    #[serde(default)]
+    pub case_insensitive_names: bool,
+    // treat untracked store files as entries deploying to $HOME/.<name>
+    #[serde(default)]
+    pub discover: bool,
+    // categories every added entry gets unless told otherwise
+    #[serde(default)]
+    pub default_categories: Vec<String>,
+    // how directory entries are linked back into place
+    #[serde(default)]
+    pub dir_links: DirLinks,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DirLinks {
+    // whatever the symlink crate picks for the target
+    #[default]
+    Auto,
+    // always an explicit directory symlink
+    Symlink,
+    // an NTFS junction, which needs no symlink privilege on Windows
+    Junction,
+}
+
+impl DirLinks {
+    pub fn parse(value: &str) -> Result<Self> {
+        return match value {
+            "auto" => Ok(DirLinks::Auto),
+            "symlink" => Ok(DirLinks::Symlink),
+            "junction" => Ok(DirLinks::Junction),
+            _ => Err(anyhow!(
+                "dir_links takes auto, symlink or junction, not {}.",
+                value
+            )),
+        };
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        return match self {
+            DirLinks::Auto => "auto",
+            DirLinks::Symlink => "symlink",
+            DirLinks::Junction => "junction",
+        };
+    }
+}
+
+// keys `config set` can change, the paths are left to init and move-store
+pub const CONFIG_KEYS: [&str; 5] = [
+    "ignore",
+    "case_insensitive_names",
+    "discover",
+    "default_categories",
+    "dir_links",
+];
+
+impl TroveConfig {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        // lists are comma separated, flags are true or false
+        let flag = || match value.parse::<bool>() {
+            Ok(b) => Ok(b),
+            Err(_) => Err(anyhow!("{} takes true or false, not {}.", key, value)),
+        };
+        match key {
+            "ignore" => self.ignore = parse_categories(value),
+            "case_insensitive_names" => self.case_insensitive_names = flag()?,
+            "discover" => self.discover = flag()?,
+            "default_categories" => self.default_categories = parse_categories(value),
+            "dir_links" => self.dir_links = DirLinks::parse(value)?,
+            _ => {
+                return Err(anyhow!(
+                    "Unknown config key {}, expected one of: {}.",
+                    key,
+                    CONFIG_KEYS.join(", ")
+                ))
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        // the settable keys with values written the way set takes them
+        return vec![
+            ("ignore", self.ignore.join(",")),
+            (
+                "case_insensitive_names",
+                self.case_insensitive_names.to_string(),
+            ),
+            ("discover", self.discover.to_string()),
+            ("default_categories", self.default_categories.join(",")),
+            ("dir_links", self.dir_links.as_str().to_string()),
+        ];
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CategoryMatch {
+    Any,
+    All,
+}
+
+impl CategoryMatch {
+    pub fn from_flag(all_categories: bool) -> Self {
+        if all_categories {
+            return CategoryMatch::All;
+        }
+        return CategoryMatch::Any;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Operation {
+    Add,
+    Remove,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub timestamp: u64,
+    pub operation: Operation,
+    pub entry: Entry,
+    pub store_path: String,
+    pub host_path: String,
+}
+
+// oldest records are dropped once the journal grows past this
+const HISTORY_LIMIT: usize = 100;
+
+// bump alongside a new step in migrate_config
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trove {
+    pub config: TroveConfig,
+    // written out by name so trove.conf diffs cleanly
+    #[serde(serialize_with = "serialize_sorted")]
+    pub entries: HashSet<Entry>,
+    // where $HOME and ~/.trove resolve to, not part of the config file
+    #[serde(skip)]
+    pub home: Home,
+}
+
+// entries left out of whatever a command selects
+#[derive(Debug, Clone, Default)]
+pub struct Exclude {
+    pub names: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+// how add_command and its recursive and list forms store a path
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    // derived from the file name when None; the prefix for recursive adds
+    pub name: Option<String>,
+    // file name in the store when it should differ from the entry name
+    pub store_name: Option<String>,
+    pub categories: Vec<String>,
+    // comma separated globs, None falls back to the trove's ignore list
+    pub ignore: Option<String>,
+    pub description: Option<String>,
+    // store a symlink itself rather than the file it points at
+    pub no_follow: bool,
+    // add even if another entry already deploys to the same place
+    pub allow_conflict: bool,
+}
+
+// asked with a directory in the way, true lets force delete it
+pub type OverwriteDir<'a> = &'a (dyn Fn(&PathBuf) -> Result<bool> + Sync);
+
+// how deploy_command and deploy_entry treat what is already at a host path
+#[derive(Clone, Default)]
+pub struct DeployOptions<'a> {
+    // replace whatever already exists at the host path
+    pub force: bool,
+    // leave correct links alone, only create missing ones and fix wrong links
+    pub only_missing: bool,
+    // fail entries missing from the store or with unset variables instead of skipping them
+    pub strict: bool,
+    // asked before force deletes a directory, without it directories are kept
+    pub overwrite_dir: Option<OverwriteDir<'a>>,
+    // deploy under this directory instead of the real host paths
+    pub target_dir: Option<PathBuf>,
+    pub exclude: Exclude,
+    // how many entries deploy places at once, below 2 it goes one by one
+    pub jobs: usize,
+}
+
+// what pack_command takes away, the counterpart of DeployOptions
+#[derive(Debug, Clone, Default)]
+pub struct PackOptions {
+    // pack links made by deploying with the same target_dir
+    pub target_dir: Option<PathBuf>,
+    pub exclude: Exclude,
+}
+
+impl DeployOptions<'_> {
+    fn confirm_overwrite(&self, dir: &PathBuf) -> Result<bool> {
+        return match self.overwrite_dir {
+            Some(ask) => ask(dir),
+            None => Ok(false),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Home {
+    // overrides the user's home directory, mostly so tests can use a temp dir
+    dir: Option<PathBuf>,
+}
+
+impl Home {
+    pub fn at(dir: PathBuf) -> Self {
+        return Home { dir: Some(dir) };
+    }
+
+    pub fn with_override(dir: Option<PathBuf>) -> Result<Self> {
+        // an explicit directory, then TROVE_HOME, then the user's real home
+        let env = std::env::var_os("TROVE_HOME").filter(|v| !v.is_empty());
+        match dir.or_else(|| env.map(PathBuf::from)) {
+            Some(dir) => return Ok(Home::at(std::env::current_dir()?.join(dir))),
+            None => return Ok(Home::default()),
+        }
+    }
+
+    pub fn dir(&self) -> Option<PathBuf> {
+        match &self.dir {
+            Some(d) => return Some(d.clone()),
+            None => return dirs_next::home_dir(),
+        }
+    }
+}
+
+// named troves for --trove, kept in ~/.config/trove/troves.json as name to config path
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Registry {
+    pub troves: BTreeMap<String, String>,
+}
+
+impl Registry {
+    pub fn path(home: &Home) -> Result<PathBuf> {
+        match home.dir() {
+            Some(dir) => return Ok(dir.join(".config/trove/troves.json")),
+            None => return Err(anyhow!("Could not find home directory.")),
+        }
+    }
+
+    pub fn load(home: &Home) -> Result<Self> {
+        // no file yet is an empty registry
+        let path = Registry::path(home)?;
+        if !path.exists() {
+            return Ok(Registry::default());
+        }
+        let cont = std::fs::read_to_string(&path)?;
+        return serde_json::from_str(&cont)
+            .with_context(|| format!("Could not read {}", path.display()));
+    }
+
+    pub fn save(&self, home: &Home) -> Result<()> {
+        let path = Registry::path(home)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        return json_to_file(&path, &serde_json::to_string_pretty(self)?);
+    }
+
+    pub fn register(&mut self, name: &str, conf: &PathBuf, home: &Home, force: bool) -> Result<()> {
+        // only a config that loads can be registered, and a taken name needs force
+        let conf = get_absolute_path(conf)?;
+        Trove::load_config(Some(conf.clone()), home)
+            .with_context(|| format!("{} is not a trove config", conf.display()))?;
+        if let (Some(old), false) = (self.troves.get(name), force) {
+            return Err(anyhow!(
+                "{} is already registered to {}, pass --force to replace it.",
+                name,
+                old
+            ));
+        }
+        self.troves
+            .insert(name.to_owned(), contract_home(&conf, home));
+        return Ok(());
+    }
+
+    pub fn resolve(&self, name: &str, home: &Home) -> Result<PathBuf> {
+        match self.troves.get(name) {
+            Some(conf) => return Ok(expand_home(conf, home)),
+            None => {
+                let known: Vec<&str> = self.troves.keys().map(|k| k.as_str()).collect();
+                return Err(anyhow!(
+                    "No trove registered as {}, known troves: {}.",
+                    name,
+                    if known.is_empty() {
+                        "none".to_owned()
+                    } else {
+                        known.join(", ")
+                    }
+                ));
+            }
+        }
+    }
+
+    pub fn default_name(&self, home: &Home) -> Option<String> {
+        // the registered trove ~/.trove currently points at
+        let current = std::fs::canonicalize(home_trove_link(home).ok()?).ok()?;
+        return self
+            .troves
+            .iter()
+            .find(|(_, conf)| {
+                std::fs::canonicalize(expand_home(conf, home)).ok() == Some(current.clone())
+            })
+            .map(|(name, _)| name.clone());
+    }
+}
+
+// what deploy_entry did with one entry, failures come back as Err
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeployOutcome {
+    // linked, copied or decrypted into place
+    Created,
+    // the host path already had it, and why that counts
+    AlreadyLinked(&'static str),
+    // something at or above the host path is in the way
+    Conflict(String),
+    // left alone without it being an error, e.g. missing from the store
+    Skipped(String),
+}
+
+// what pack_entry did with one entry, failures come back as Err
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackOutcome {
+    Packed,
+    Skipped(&'static str),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    // entries the command went through with
+    pub done: Vec<String>,
+    // entries that failed, and why
+    pub failed: Vec<(String, String)>,
+    // entries left alone, and why
+    pub skipped: Vec<(String, String)>,
+    // anything worth telling the user that didn't stop the command
+    pub notes: Vec<String>,
+}
+
+// results of the read commands for --json output, field names are a stable interface
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusSummary {
+    pub config: PathBuf,
+    pub store: PathBuf,
+    pub entries: usize,
+    pub deployed: usize,
+    pub broken: usize,
+    pub categories: Vec<CategoryCount>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryCount {
+    pub name: String,
+    pub entries: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryListing {
+    pub name: String,
+    pub store_path: PathBuf,
+    pub host_path: String,
+    pub categories: Vec<String>,
+    pub description: Option<String>,
+    pub added_at: Option<String>,
+    pub origin: Option<String>,
+    pub deployed: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub entries: usize,
+    pub deployed: usize,
+    pub categories: Vec<CategoryCount>,
+    // bytes and regular files across every entry's store path
+    pub store_bytes: u64,
+    pub files: usize,
+    // biggest entries first
+    pub largest: Vec<EntrySize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntrySize {
+    pub name: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyResult {
+    pub ok: Vec<String>,
+    pub problems: Vec<Problem>,
+}
+
+impl From<&Report> for VerifyResult {
+    fn from(report: &Report) -> Self {
+        let problems = report
+            .failed
+            .iter()
+            .map(|(name, reason)| Problem {
+                name: name.clone(),
+                reason: reason.clone(),
+            })
+            .collect();
+        return VerifyResult {
+            ok: report.done.clone(),
+            problems,
+        };
+    }
+}
+
+// a deploy or pack written out with --report, for collecting outcomes from many machines
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    pub action: String,
+    pub hostname: Option<String>,
+    pub timestamp: String,
+    pub entries: Vec<EntryOutcome>,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EntryOutcome {
+    pub name: String,
+    // created or packed, skipped, or failed
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl RunReport {
+    pub fn new(action: &str, report: &Report) -> Result<Self> {
+        // done entries are named after what the action does to them, as in DeployOutcome
+        let done = if action == "pack" {
+            "packed"
+        } else {
+            "created"
+        };
+        let mut entries: Vec<EntryOutcome> = report
+            .done
+            .iter()
+            .map(|name| EntryOutcome {
+                name: name.clone(),
+                outcome: done,
+                reason: None,
+            })
+            .collect();
+        for (outcome, list) in [("skipped", &report.skipped), ("failed", &report.failed)] {
+            for (name, reason) in list {
+                entries.push(EntryOutcome {
+                    name: name.clone(),
+                    outcome,
+                    reason: Some(reason.clone()),
+                });
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        return Ok(RunReport {
+            action: action.to_owned(),
+            hostname: hostname(),
+            timestamp: rfc3339(unix_now()?),
+            entries,
+            notes: report.notes.clone(),
+        });
+    }
+
+    pub fn write(&self, path: &PathBuf) -> Result<()> {
+        return json_to_file(path, &serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Could not write the report to {}", path.display()));
+    }
+}
+
+impl Report {
+    fn fail(&mut self, action: &str, name: &str, err: anyhow::Error) {
+        // the alternate form keeps the whole context chain, e.g. which path failed
+        log::warn!("Could not {} {}: {:#}", action, name, err);
+        self.failed.push((name.to_owned(), format!("{:#}", err)));
+    }
+
+    fn skip(&mut self, name: &str, reason: &str) {
+        log::info!("Skipping {}: {}", name, reason);
+        self.skipped.push((name.to_owned(), reason.to_owned()));
+    }
+
+    fn merge(&mut self, other: Report) {
+        self.done.extend(other.done);
+        self.failed.extend(other.failed);
+        self.skipped.extend(other.skipped);
+        self.notes.extend(other.notes);
+    }
+
+    pub fn summary(&self, verb: &str) -> String {
+        // e.g. "Deployed 12, skipped 2 (already linked), 1 failed."
+        let mut out = format!("{} {}", verb, self.done.len());
+        if !self.skipped.is_empty() {
+            let mut reasons: Vec<(&str, usize)> = Vec::new();
+            for (_, reason) in &self.skipped {
+                match reasons.iter_mut().find(|(r, _)| r == reason) {
+                    Some((_, n)) => *n += 1,
+                    None => reasons.push((reason, 1)),
+                }
+            }
+            let why = if reasons.len() == 1 {
+                reasons[0].0.to_owned()
+            } else {
+                let parts: Vec<String> = reasons
+                    .iter()
+                    .map(|(r, n)| format!("{} {}", n, r))
+                    .collect();
+                parts.join(", ")
+            };
+            out.push_str(&format!(", skipped {} ({})", self.skipped.len(), why));
+        }
+        if !self.failed.is_empty() {
+            out.push_str(&format!(", {} failed", self.failed.len()));
+        }
+        out.push('.');
+        return out;
+    }
+
+    pub fn check(&self, action: &str) -> Result<()> {
+        // rolls up per-entry failures from a bulk command into one error
+        if self.failed.is_empty() {
+            return Ok(());
+        }
+        let mut names: Vec<&str> = self.failed.iter().map(|(n, _)| n.as_str()).collect();
+        names.sort();
+        return Err(anyhow!(
+            "Could not {} {} entries: {}",
+            action,
+            names.len(),
+            names.join(", ")
+        ));
+    }
+}
+
+impl Trove {
+    pub fn find_entry_by_name(&self, name: &str) -> Option<Entry> {
+        for e in &self.entries {
+            let matched = if self.config.case_insensitive_names {
+                e.name.to_lowercase() == name.to_lowercase()
+            } else {
+                e.name == name
+            };
+            if matched {
+                return Some(e.clone());
+            }
+        }
+        return None;
+    }
+
+    pub fn find_entry_by_path(&self, path: &PathBuf) -> Option<Entry> {
+        // a deployed host path is a symlink into the store, so compare resolved paths as well
+        let path = normalize_path(path);
+        let target = std::fs::canonicalize(&path).unwrap_or(path.clone());
+        for e in &self.entries {
+            let host = expand_home(&e.host_path, &self.home);
+            if normalize_path(&host) == path {
+                return Some(e.clone());
+            }
+            if let Ok(resolved) = std::fs::canonicalize(&host) {
+                if resolved == target {
+                    return Some(e.clone());
+                }
+            }
+        }
+        return None;
+    }
+
+    pub fn find_entry_by_category(
+        &self,
+        category: &String,
+        mode: CategoryMatch,
+    ) -> Option<HashSet<Entry>> {
+        // a comma separated list matches entries in any (or all) of the categories
+        let wanted = parse_categories(category);
+        let mut out = HashSet::new();
+        for e in &self.entries {
+            let has = |c: &String| e.categories.contains(c);
+            let matched = match mode {
+                CategoryMatch::Any => wanted.iter().any(has),
+                CategoryMatch::All => !wanted.is_empty() && wanted.iter().all(has),
+            };
+            if matched {
+                out.insert(e.to_owned());
+            }
+        }
+        if out.is_empty() {
+            return None;
+        } else {
+            return Some(out);
+        }
+    }
+
+    fn find_config(p: Option<PathBuf>, home: &Home) -> Result<PathBuf> {
+        // an explicit path, then a trove.conf in or above the working directory, then ~/.trove
+        let found = match &p {
+            Some(_) => None,
+            None => find_conf_upward(&std::env::current_dir()?),
+        };
+        let conf = match p.or(found) {
+            Some(path) => path,
+            None => {
+                let link = home_trove_link(home)?;
+                if let Some(target) = stale_link_target(&link) {
+                    return Err(anyhow!(
+                        "{} points to {}, which no longer exists. \r\n Run `trove init --repair <path>` to re-point it.",
+                        link.display(),
+                        target.display()
+                    ));
+                }
+                link
+            }
+        };
+        if let Ok(path) = get_absolute_path(&conf) {
+            return Ok(path);
+        }
+        return Err(anyhow!(
+            "Could not find a valid .trove file. \r\n Run `trove init <path>` to begin."
+        ));
+    }
+
+    pub fn load(p: Option<PathBuf>, home: Home) -> Result<Self> {
+        let path = Trove::find_config(p, &home)?;
+        let mut json = json_from_file(&path)?;
+        let migrated = migrate_config(&mut json)?;
+        let mut trove: Trove = serde_json::from_value(json)?;
+        trove.home = home;
+        if migrated {
+            // write back to the file that was read, ~/.trove may be a link to it
+            let cont = serde_json::to_string_pretty(&trove)?;
+            json_to_file(&path, &cont)?;
+            log::info!("Upgraded {} to version {}", path.display(), CONFIG_VERSION);
+        }
+        if trove.config.discover {
+            trove.discover_entries()?;
+        }
+        return Ok(trove);
+    }
+
+    pub fn load_config(p: Option<PathBuf>, home: &Home) -> Result<TroveConfig> {
+        // only reads the config section, so broken entries don't get in the way
+        let path = Trove::find_config(p, home)?;
+        let mut json = json_from_file(&path)?;
+        migrate_config(&mut json)?;
+        let config: TroveConfig = serde_json::from_value(json["config"].take())?;
+        return Ok(config);
+    }
+
+    pub fn create(path: PathBuf, home: Home) -> Result<Self> {
+        // create the trove.conf file
+        let mut conf = path.clone();
+        conf.push("trove.conf");
+        return Trove::create_with_config(path, conf, home);
+    }
+
+    pub fn create_with_config(path: PathBuf, conf: PathBuf, home: Home) -> Result<Self> {
+        // the config file may live outside the trove directory, e.g. to keep it out of a repo
+        let mut store = path.clone();
+        store.push("store");
+        let trove = Trove {
+            config: TroveConfig {
+                version: CONFIG_VERSION,
+                path: contract_home(&conf, &home),
+                store_path: contract_home(&store.clone(), &home),
+                ignore: Vec::new(),
+                case_insensitive_names: false,
+                discover: false,
+                default_categories: Vec::new(),
+                dir_links: DirLinks::Auto,
+            },
+            entries: HashSet::new(),
+            home,
+        };
+
+        // an existing store directory is reused, anything else in the way is an error
+        match std::fs::create_dir_all(&store) {
+            Ok(_) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists && store.is_dir() => {}
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Could not create the store at {}", store.display()))
+            }
+        }
+
+        if let Some(parent) = conf.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let cont = serde_json::to_string_pretty(&trove)?;
+        json_to_file(&expand_home(&trove.config.path, &trove.home), &cont)?;
+
+        return Ok(trove);
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut kept = self.clone();
+        kept.entries.retain(|e| !e.discovered);
+        let cont = serde_json::to_string_pretty(&kept)?;
+        json_to_file(&expand_home(&self.config.path, &self.home), &cont)?;
+
+        return Ok(());
+    }
+
+    pub fn create_conf_symlink(&self) -> Result<bool> {
+        // create symlink to home dir, false if something is already there
+        if let Ok(home) = home_trove_link(&self.home) {
+            match symlink::symlink_file(expand_home(&self.config.path, &self.home), home) {
+                Ok(_) => Ok(true),
+                Err(_) => Ok(false),
+            }
+        } else {
+            return Err(anyhow!("Could not find home directory."));
+        }
+    }
+
+    pub fn replace_conf_symlink(&self) -> Result<Option<PathBuf>> {
+        // point ~/.trove at this trove even if it is linked elsewhere, returning the old target
+        let link = home_trove_link(&self.home)?;
+        let old = match std::fs::symlink_metadata(&link) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let old = std::fs::read_link(&link)?;
+                symlink::remove_symlink_file(&link)?;
+                Some(old)
+            }
+            Ok(_) => {
+                return Err(anyhow!(
+                    "{} is not a symlink, refusing to replace it.",
+                    link.display()
+                ))
+            }
+            Err(_) => None,
+        };
+        symlink::symlink_file(expand_home(&self.config.path, &self.home), &link)?;
+        return Ok(old);
+    }
+
+    pub fn repair_conf_symlink(&mut self, conf: &PathBuf) -> Result<Option<PathBuf>> {
+        // replace a dangling ~/.trove with a link to this trove's config, returning the old target
+        let link = home_trove_link(&self.home)?;
+        let stale = stale_link_target(&link);
+        match &stale {
+            Some(_) => {
+                symlink::remove_symlink_file(&link)?;
+            }
+            None => {
+                if std::fs::symlink_metadata(&link).is_ok() {
+                    return Err(anyhow!(
+                        "{} is not a stale link, refusing to repair.",
+                        link.display()
+                    ));
+                }
+            }
+        }
+        self.relocate(conf)?;
+        self.create_conf_symlink()?;
+        return Ok(stale);
+    }
+
+    pub fn relocate(&mut self, conf: &PathBuf) -> Result<()> {
+        // the trove may have moved since its config was written
+        let found = contract_home(conf, &self.home);
+        if self.config.path != found {
+            self.config.path = found;
+            let mut store = conf.clone();
+            store.set_file_name("store");
+            if !expand_home(&self.config.store_path, &self.home).exists() && store.is_dir() {
+                self.config.store_path = contract_home(&store, &self.home);
+            }
+            self.save()?;
+        }
+        return Ok(());
+    }
+
+    pub fn import_git(url: &str, dest: &PathBuf, home: Home) -> Result<Self> {
+        // clone a dotfile repo and load the trove inside it
+        let status = std::process::Command::new("git")
+            .arg("clone")
+            .arg(url)
+            .arg(dest)
+            .status()
+            .context("Could not run git")?;
+        if !status.success() {
+            return Err(anyhow!("git clone of {} failed.", url));
+        }
+        let dest = get_absolute_path(dest)?;
+        let conf = dest.join("trove.conf");
+        if !conf.is_file() {
+            return Err(anyhow!("No trove.conf found in {}.", dest.display()));
+        }
+        let mut trove = Trove::load(Some(conf.clone()), home)?;
+        // a store kept inside the repo moves along with the clone
+        let old_conf = expand_home(&trove.config.path, &trove.home);
+        let old_store = expand_home(&trove.config.store_path, &trove.home);
+        if let Some(old_dir) = old_conf.parent() {
+            if let Ok(rel) = old_store.strip_prefix(old_dir) {
+                let store = dest.join(rel);
+                if store.is_dir() {
+                    trove.config.store_path = contract_home(&store, &trove.home);
+                }
+            }
+        }
+        trove.relocate(&conf)?;
+        trove.save()?;
+        return Ok(trove);
+    }
+
+    pub fn with_default_categories(&self, categories: &[String]) -> Vec<String> {
+        // the given categories first, then any configured defaults not already there
+        let mut out = categories.to_vec();
+        for c in &self.config.default_categories {
+            if !out.contains(c) {
+                out.push(c.clone());
+            }
+        }
+        return out;
+    }
+
+    pub fn add_entry(&mut self, path: PathBuf, opts: &AddOptions) -> Result<()> {
+        // records the entry only, opts.ignore holds the patterns the caller settled on
+        let name = &match &opts.name {
+            Some(n) => n.clone(),
+            None => derive_name(&path)?,
+        };
+        // the name becomes a file name in the store
+        check_nested_name(name)?;
+        if let Some(stored_name) = &opts.store_name {
+            check_nested_name(stored_name)?;
+        }
+        // check if the name is already loaded
+        if let Some(_) = self.find_entry_by_name(name) {
+            return Err(TroveError::DuplicateName(name.into()).into());
+        }
+        // the caller has already decided whether to follow a symlink
+        let host_path = normalize_path(&get_absolute_path_no_follow(&path)?);
+
+        let entry = Entry {
+            name: name.into(),
+            host_path: contract_home(&host_path, &self.home),
+            categories: opts.categories.clone(),
+            ignore: opts
+                .ignore
+                .as_deref()
+                .map(parse_categories)
+                .unwrap_or_default(),
+            mode: file_mode(&host_path),
+            description: opts.description.clone(),
+            added_at: Some(rfc3339(unix_now()?)),
+            origin: hostname(),
+            encrypted: false,
+            store_name: opts.store_name.clone(),
+            content_hash: None,
+            post_link: None,
+            externalized: false,
+            discovered: false,
+        };
+        let dest = self.destination(&entry);
+        if let Some(other) = self.entries.iter().find(|e| self.destination(e) == dest) {
+            if !opts.allow_conflict {
+                return Err(TroveError::DestinationConflict {
+                    entry: other.name.clone(),
+                    path: dest,
+                }
+                .into());
+            }
+            log::warn!(
+                "{} and {} both deploy to {}",
+                &other.name,
+                name,
+                dest.display()
+            );
+        }
+
+        self.entries.insert(entry);
+        self.save()?;
+
+        return Ok(());
+    }
+
+    fn destination(&self, entry: &Entry) -> PathBuf {
+        // the host path with its parent resolved, the path itself is usually a link
+        let host = expand_home(&entry.host_path, &self.home);
+        let parent = host.parent().and_then(|p| std::fs::canonicalize(p).ok());
+        return match (parent, host.file_name()) {
+            (Some(parent), Some(file_name)) => parent.join(file_name),
+            _ => host,
+        };
+    }
+
+    pub fn entries_sorted(&self) -> Vec<&Entry> {
+        // the set has no order, so anything shown or done in bulk goes by name
+        let mut entries: Vec<&Entry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        return entries;
+    }
+
+    pub fn find_destination_conflicts(&self) -> Vec<(String, String)> {
+        // pairs of entry names that deploy to the same place, sorted by name
+        let entries = self.entries_sorted();
+        let dests: Vec<PathBuf> = entries.iter().map(|e| self.destination(e)).collect();
+        let mut conflicts = Vec::new();
+        for i in 0..entries.len() {
+            for j in i + 1..entries.len() {
+                if dests[i] == dests[j] {
+                    conflicts.push((entries[i].name.clone(), entries[j].name.clone()));
+                }
+            }
+        }
+        return conflicts;
+    }
+
+    pub fn is_stale(&self, entry: &Entry) -> bool {
+        // nothing left of it: no store file and no host path, or only a dangling link there
+        return !self.stored_path(entry).exists()
+            && !expand_home(&entry.host_path, &self.home).exists();
+    }
+
+    pub fn replace_stale_entry(&mut self, name: &str) -> Result<Option<Entry>> {
+        // drops a stale entry so its name can be added again, returning it
+        let entry = match self.find_entry_by_name(name) {
+            Some(e) => e,
+            None => return Ok(None),
+        };
+        if !self.is_stale(&entry) {
+            return Err(anyhow!(
+                "Entry {} is not stale, its store file or host path still exists.",
+                name
+            ));
+        }
+        let host_path = expand_home(&entry.host_path, &self.home);
+        if std::fs::symlink_metadata(&host_path).is_ok() {
+            symlink::remove_symlink_auto(&host_path)?;
+        }
+        self.remove_entry(&entry)?;
+        return Ok(Some(entry));
+    }
+
+    pub fn remove_entry(&mut self, entry: &Entry) -> Result<()> {
+        self.entries.remove(entry);
+
+        self.save()?;
+        Ok(())
+    }
+
+    pub fn add_command(&mut self, path: &PathBuf, opts: &AddOptions) -> Result<Entry> {
+        // a name given by the caller is a single file name in the store
+        for name in opts.name.iter().chain(&opts.store_name) {
+            check_entry_name(name)?;
+        }
+        return self.add_path(path, opts);
+    }
+
+    fn add_path(&mut self, path: &PathBuf, opts: &AddOptions) -> Result<Entry> {
+        // a quoted or piped ~/ never went through the shell's expansion
+        let path = &expand_tilde(path, &self.home);
+        let store_name = &opts.store_name;
+        let from_path = if opts.no_follow {
+            get_absolute_path_no_follow(path)?
+        } else {
+            get_absolute_path(path)?
+        };
+        let name = match &opts.name {
+            Some(n) => n.clone(),
+            None => derive_name(&from_path)?,
+        };
+        let name = &name;
+        let store = get_absolute_path(&expand_home(&self.config.store_path, &self.home))?;
+        if from_path.starts_with(&store) {
+            // a deployed entry's host path resolves into the store, name the entry instead
+            let host_path = normalize_path(&std::env::current_dir()?.join(path));
+            if let Some(e) = self.find_entry_by_path(&host_path) {
+                return Err(anyhow::Error::from(TroveError::DuplicatePath(host_path))
+                    .context(format!("Already added as {}", &e.name)));
+            }
+            return Err(TroveError::InStore {
+                path: from_path,
+                store,
+            }
+            .into());
+        }
+        let stored_name = store_name.as_ref().unwrap_or(name);
+        check_nested_name(stored_name)?;
+        if let Some(e) = self
+            .entries
+            .iter()
+            .find(|e| e.stored_name() == stored_name && &e.name != name)
+        {
+            return Err(anyhow!("{} is already stored as {}.", &e.name, stored_name));
+        }
+        // linked through the configured store path, which may itself be a link to another volume
+        let mut to_path = normalize_path(&expand_home(&self.config.store_path, &self.home));
+        to_path.push(stored_name);
+        // ignore patterns only apply to directories, files are always moved
+        let patterns = match &opts.ignore {
+            Some(s) => parse_categories(s),
+            None => self.config.ignore.clone(),
+        };
+        // a directory is only copied when something in it is actually ignored
+        let is_dir = std::fs::symlink_metadata(&from_path)?.is_dir();
+        let patterns = if is_dir && has_ignored(&from_path, &PathBuf::new(), &patterns)? {
+            patterns
+        } else {
+            Vec::new()
+        };
+        let recorded = AddOptions {
+            name: Some(name.clone()),
+            ignore: Some(patterns.join(",")),
+            ..opts.clone()
+        };
+        self.add_entry(from_path.clone(), &recorded)?;
+        // names with a slash are kept in a matching subdirectory of the store
+        let stored = match to_path.parent() {
+            Some(parent) => std::fs::create_dir_all(parent).map_err(anyhow::Error::from),
+            None => Ok(()),
+        }
+        .and_then(|_| {
+            if patterns.is_empty() {
+                return store_and_link(&from_path, &to_path, &|to, from| {
+                    link_path(to, from, self.config.dir_links)
+                });
+            }
+            // copy what isn't ignored and leave the host directory in place
+            return copy_tree(&from_path, &to_path, &patterns);
+        });
+        // the entry is already saved, drop it again if the file couldn't be stored
+        if let Err(err) = stored {
+            if let Some(entry) = self.find_entry_by_name(name) {
+                self.remove_entry(&entry)?;
+            }
+            return Err(err);
+        }
+
+        let mut entry = match self.find_entry_by_name(name) {
+            Some(e) => e,
+            None => return Err(anyhow!("Entry {} was not saved.", name)),
+        };
+        // a copy stays at the host path and can be edited there without the store noticing
+        if entry.is_copy() {
+            entry.content_hash = Some(content_hash(&from_path, &entry.ignore)?);
+            self.entries.replace(entry.clone());
+            self.save()?;
+        }
+        self.record(Operation::Add, &entry)?;
+
+        return Ok(entry);
+    }
+
+    pub fn add_recursive_command(
+        &mut self,
+        path: &PathBuf,
+        depth: usize,
+        opts: &AddOptions,
+    ) -> Result<Report> {
+        // each child of the directory, down to depth, becomes its own entry named
+        // under opts.name; children are always followed and keep their own names in the store
+        let dir = get_absolute_path(path)?;
+        if !dir.is_dir() {
+            return Err(TroveError::PathDoesNotExist(dir).into());
+        }
+        let prefix = match &opts.name {
+            Some(n) => n.clone(),
+            None => derive_name(&dir)?,
+        };
+        check_entry_name(&prefix)?;
+        let patterns = match &opts.ignore {
+            Some(s) => parse_categories(s),
+            None => self.config.ignore.clone(),
+        };
+        let mut children = Vec::new();
+        collect_children(
+            &dir,
+            &PathBuf::new(),
+            depth.max(1),
+            &patterns,
+            &mut children,
+        )?;
+        if children.is_empty() {
+            return Err(anyhow!("Nothing to add in {}.", dir.display()));
+        }
+        let mut report = Report::default();
+        for rel in children {
+            let child_name = format!("{}/{}", prefix, rel.to_string_lossy());
+            // children are moved whole, only copied if something inside them is ignored
+            let child_ignore = match has_ignored(&dir.join(&rel), &PathBuf::new(), &patterns)? {
+                true => Some(patterns.join(",")),
+                false => Some(String::new()),
+            };
+            let child = AddOptions {
+                name: Some(child_name.clone()),
+                store_name: None,
+                ignore: child_ignore,
+                no_follow: false,
+                ..opts.clone()
+            };
+            let added = self.add_path(&dir.join(&rel), &child);
+            match added {
+                Ok(_) => report.done.push(child_name),
+                Err(err) => report.fail("add", &child_name, err),
+            }
+        }
+        return Ok(report);
+    }
+
+    pub fn add_list_command(&mut self, list: &str, opts: &AddOptions) -> Report {
+        // one path per line, or path<TAB>name<TAB>categories, carrying on past failures;
+        // blank lines and # comments are skipped, a line's name and categories add to opts
+        let mut report = Report::default();
+        for (i, line) in list.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let label = format!("line {} ({})", i + 1, fields[0]);
+            if fields.len() > 3 {
+                report.fail(
+                    "add",
+                    &label,
+                    anyhow!("expected at most 3 tab separated fields"),
+                );
+                continue;
+            }
+            // an empty path would resolve to the working directory
+            if fields[0].trim().is_empty() {
+                report.fail("add", &label, anyhow!("the path field is empty"));
+                continue;
+            }
+            let name = fields
+                .get(1)
+                .map(|n| n.trim())
+                .filter(|n| !n.is_empty())
+                .map(String::from);
+            let mut line = AddOptions {
+                name,
+                store_name: None,
+                ..opts.clone()
+            };
+            for c in parse_categories(fields.get(2).unwrap_or(&"")) {
+                if !line.categories.contains(&c) {
+                    line.categories.push(c);
+                }
+            }
+            let added = self.add_command(&PathBuf::from(fields[0].trim()), &line);
+            match added {
+                Ok(e) => report.done.push(e.name),
+                Err(err) => report.fail("add", &label, err),
+            }
+        }
+        return report;
+    }
+
+    pub fn track_command(
+        &mut self,
+        path: &PathBuf,
+        host: &PathBuf,
+        opts: &AddOptions,
+    ) -> Result<Entry> {
+        // tracks a file already laid out in the store, only the host symlink is created
+        let store = get_absolute_path(&expand_home(&self.config.store_path, &self.home))?;
+        let from_path = match get_absolute_path(path) {
+            Ok(p) => p,
+            Err(_) => return Err(anyhow!("{} is not in the store.", path.display())),
+        };
+        let rel = match from_path.strip_prefix(&store) {
+            Ok(rel) if !rel.as_os_str().is_empty() => rel.to_string_lossy().to_string(),
+            _ => {
+                return Err(anyhow!(
+                    "{} is not inside the store at {}, add it without --move=false.",
+                    from_path.display(),
+                    store.display()
+                ))
+            }
+        };
+        // the name is where the file sits, otherwise deploy would look elsewhere
+        if let Some(n) = &opts.name {
+            check_nested_name(n)?;
+            if n != &rel {
+                return Err(anyhow!(
+                    "{} would be stored as {}, not {}.",
+                    from_path.display(),
+                    rel,
+                    n
+                ));
+            }
+        }
+        if let Some(_) = self.find_entry_by_name(&rel) {
+            return Err(TroveError::DuplicateName(rel).into());
+        }
+        let host_path = std::env::current_dir()?.join(host);
+        if let Some(parent) = host_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let from_path =
+            normalize_path(&expand_home(&self.config.store_path, &self.home)).join(&rel);
+        let linked = match std::fs::read_link(&host_path) {
+            Ok(target) if target == from_path => false,
+            _ if std::fs::symlink_metadata(&host_path).is_ok() => {
+                return Err(TroveError::DuplicatePath(host_path).into())
+            }
+            _ => {
+                link_path(&from_path, &host_path, self.config.dir_links)?;
+                true
+            }
+        };
+        let tracked = AddOptions {
+            name: Some(rel.clone()),
+            store_name: None,
+            ignore: None,
+            ..opts.clone()
+        };
+        let added = self.add_entry(host_path.clone(), &tracked);
+        if let Err(err) = added {
+            if linked {
+                symlink::remove_symlink_auto(&host_path)?;
+            }
+            return Err(err);
+        }
+        // not recorded, undoing an add would move the file out of the store
+        match self.find_entry_by_name(&rel) {
+            Some(e) => return Ok(e),
+            None => return Err(anyhow!("Entry {} was not saved.", rel)),
+        }
+    }
+
+    pub fn encrypt_command(&mut self, path: &PathBuf, opts: &AddOptions) -> Result<Entry> {
+        // stores an encrypted copy, the plaintext stays at the host path
+        let key = trove_key(&self.home)?;
+        let from_path = get_absolute_path(path)?;
+        if !from_path.is_file() {
+            return Err(anyhow!(
+                "{} is not a file, only files can be encrypted.",
+                from_path.display()
+            ));
+        }
+        let name = match &opts.name {
+            Some(n) => n.clone(),
+            None => derive_name(&from_path)?,
+        };
+        let encrypted = AddOptions {
+            name: Some(name.clone()),
+            store_name: None,
+            ignore: None,
+            ..opts.clone()
+        };
+        self.add_entry(from_path.clone(), &encrypted)?;
+        let mut entry = match self.find_entry_by_name(&name) {
+            Some(e) => e,
+            None => return Err(anyhow!("Entry {} was not saved.", name)),
+        };
+        let to_path = self.stored_path(&entry);
+        if let Some(parent) = to_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if let Err(err) = age_encrypt(&from_path, &to_path, &key) {
+            self.remove_entry(&entry)?;
+            return Err(err);
+        }
+        entry.encrypted = true;
+        entry.content_hash = Some(content_hash(&from_path, &[])?);
+        self.entries.replace(entry.clone());
+        self.save()?;
+        self.record(Operation::Add, &entry)?;
+        return Ok(entry);
+    }
+
+    pub fn stored_path(&self, entry: &Entry) -> PathBuf {
+        let mut path = expand_home(&self.config.store_path, &self.home);
+        path.push(entry.stored_name());
+        return path;
+    }
+
+    pub fn display_stored_path(&self, entry: &Entry, store_relative: bool) -> PathBuf {
+        // strips the resolved store root so output matches across machines
+        let stored = self.stored_path(entry);
+        if !store_relative {
+            return stored;
+        }
+        let root = expand_home(&self.config.store_path, &self.home);
+        let root = std::fs::canonicalize(&root).unwrap_or(root);
+        let stored = match stored.parent().map(std::fs::canonicalize) {
+            Some(Ok(parent)) => parent.join(entry.stored_name()),
+            _ => stored,
+        };
+        match stored.strip_prefix(&root) {
+            Ok(rel) => return rel.to_path_buf(),
+            Err(_) => return stored,
+        }
+    }
+
+    fn history_path(&self) -> PathBuf {
+        // the journal lives next to trove.conf
+        let mut path = expand_home(&self.config.path, &self.home);
+        path.set_file_name("history");
+        return path;
+    }
+
+    pub fn read_history(&self) -> Result<Vec<HistoryRecord>> {
+        let path = self.history_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let cont = std::fs::read_to_string(path)?;
+        let mut records = Vec::new();
+        for line in cont.lines().filter(|l| !l.trim().is_empty()) {
+            records.push(serde_json::from_str(line)?);
+        }
+        return Ok(records);
+    }
+
+    fn write_history(&self, records: &[HistoryRecord]) -> Result<()> {
+        // write to a temp file and rename so the journal is never half written
+        let path = self.history_path();
+        let mut tmp = path.clone();
+        tmp.set_file_name("history.tmp");
+        let mut cont = String::new();
+        for r in records {
+            cont.push_str(&serde_json::to_string(r)?);
+            cont.push('\n');
+        }
+        json_to_file(&tmp, &cont)?;
+        std::fs::rename(tmp, path)?;
+        return Ok(());
+    }
+
+    fn record(&self, operation: Operation, entry: &Entry) -> Result<()> {
+        let mut store_path = self.config.store_path.clone();
+        store_path.push('/');
+        store_path.push_str(entry.stored_name());
+        let record = HistoryRecord {
+            timestamp: unix_now()?,
+            operation,
+            entry: entry.clone(),
+            store_path,
+            host_path: entry.host_path.clone(),
+        };
+        let mut records = self.read_history()?;
+        if records.len() < HISTORY_LIMIT {
+            // append so earlier lines are never rewritten, only trimming does that
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.history_path())?;
+            writeln!(file, "{}", serde_json::to_string(&record)?)?;
+            return Ok(());
+        }
+        records.push(record);
+        if records.len() > HISTORY_LIMIT {
+            records.drain(..records.len() - HISTORY_LIMIT);
+        }
+        return self.write_history(&records);
+    }
+
+    pub fn log_command(&self, limit: Option<usize>) -> Result<Vec<HistoryRecord>> {
+        // journal records, most recent first
+        let mut records = self.read_history()?;
+        records.reverse();
+        if let Some(n) = limit {
+            records.truncate(n);
+        }
+        return Ok(records);
+    }
+
+    pub fn undo_command(&mut self) -> Result<HistoryRecord> {
+        let mut records = self.read_history()?;
+        let last = match records.pop() {
+            Some(r) => r,
+            None => return Err(anyhow!("Nothing to undo.")),
+        };
+        let store_path = expand_home(&last.store_path, &self.home);
+        let host_path = expand_home(&last.host_path, &self.home);
+        match last.operation {
+            Operation::Add => {
+                // unlink, move the file back out of the store, then forget the entry
+                let entry = match self.find_entry_by_name(&last.entry.name) {
+                    Some(e) => e,
+                    None => return Err(anyhow!("Entry {} no longer exists.", &last.entry.name)),
+                };
+                if !store_path.exists() {
+                    return Err(anyhow!(
+                        "{} is missing from the store.",
+                        store_path.display()
+                    ));
+                }
+                if entry.is_copy() {
+                    // the host directory was never moved, drop the copy
+                    std::fs::remove_dir_all(&store_path)?;
+                } else if entry.encrypted {
+                    // the plaintext never left the host path
+                    std::fs::remove_file(&store_path)?;
+                } else {
+                    if let Err(_) = symlink::remove_symlink_auto(&host_path) {
+                        log::info!("Symlink does not exists, continuing...");
+                    }
+                    move_path(&store_path, &host_path)?;
+                }
+                self.remove_entry(&entry)?;
+            }
+            Operation::Remove => {
+                // move the file back into the store, relink it, then restore the entry
+                if let Some(_) = self.find_entry_by_name(&last.entry.name) {
+                    return Err(TroveError::DuplicateName(last.entry.name.clone()).into());
+                }
+                if last.entry.is_copy() {
+                    copy_tree(&host_path, &store_path, &last.entry.ignore)?;
+                } else if last.entry.encrypted {
+                    age_encrypt(&host_path, &store_path, &trove_key(&self.home)?)?;
+                } else {
+                    move_path(&host_path, &store_path)?;
+                    if let Err(err) = symlink::symlink_auto(&store_path, &host_path) {
+                        move_path(&store_path, &host_path)?;
+                        return Err(err.into());
+                    }
+                }
+                self.entries.insert(last.entry.clone());
+                self.save()?;
+            }
+        }
+        // only drop the record once its inverse has fully applied
+        self.write_history(&records)?;
+        return Ok(last);
+    }
+
+    pub fn deploy_path(&self, entry: &Entry, target_dir: &Option<PathBuf>) -> PathBuf {
+        // the host path, rebased under target_dir when deploying into a sandbox
+        let host_path = expand_home(&entry.host_path, &self.home);
+        let target = match target_dir {
+            Some(t) => t,
+            None => return host_path,
+        };
+        let in_home = self
+            .home
+            .dir()
+            .and_then(|dir| host_path.strip_prefix(dir).ok().map(Path::to_path_buf));
+        return match in_home {
+            Some(rest) => target.join(rest),
+            // paths outside home keep their full path under the sandbox
+            None => target.join(host_path.strip_prefix("/").unwrap_or(&host_path)),
+        };
+    }
+
+    pub fn deploy_entry(
+        &self,
+        entry: &Entry,
+        opts: &DeployOptions,
+        notes: &mut Vec<String>,
+    ) -> Result<DeployOutcome> {
+        // puts one entry in place, Err when it should have been deployed but couldn't be
+        let from_path = self.stored_path(entry);
+        let host_path = self.deploy_path(entry, &opts.target_dir);
+        // an externalized file only comes back from its real host path
+        let outcome = if opts.target_dir.is_none() && self.is_externalized(entry) {
+            self.readopt_entry(entry, &from_path, &host_path)?
+        } else {
+            self.check_and_place(entry, &from_path, &host_path, opts, notes)?
+        };
+        if let (DeployOutcome::Created, Some(cmd)) = (&outcome, &entry.post_link) {
+            // the link stays either way, a failing command only fails the entry with --strict
+            if let Err(err) = run_post_link(cmd, entry, &host_path) {
+                if opts.strict {
+                    return Err(err);
+                }
+                log::warn!("{:#}", err);
+                notes.push(format!("{:#}", err));
+            }
+        }
+        return Ok(outcome);
+    }
+
+    pub fn is_externalized(&self, entry: &Entry) -> bool {
+        // still externalized only while the host path holds the real file
+        let host_path = self.deploy_path(entry, &None);
+        return entry.externalized
+            && std::fs::symlink_metadata(host_path).is_ok_and(|m| !m.file_type().is_symlink());
+    }
+
+    fn readopt_entry(
+        &self,
+        entry: &Entry,
+        from_path: &PathBuf,
+        host_path: &PathBuf,
+    ) -> Result<DeployOutcome> {
+        // the reverse of pack --restore: back into the store, then linked as usual
+        if std::fs::symlink_metadata(from_path).is_ok() {
+            return Ok(DeployOutcome::Conflict(format!(
+                "{} is externalized but {} exists",
+                &entry.name,
+                from_path.display()
+            )));
+        }
+        if let Some(parent) = from_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        move_path(host_path, from_path)?;
+        if let Err(err) = link_path(from_path, host_path, self.config.dir_links) {
+            move_path(from_path, host_path)?;
+            return Err(err).with_context(|| format!("at {}", host_path.display()));
+        }
+        log::info!("Moved {} back into the store", &entry.name);
+        return Ok(DeployOutcome::Created);
+    }
+
+    fn check_and_place(
+        &self,
+        entry: &Entry,
+        from_path: &PathBuf,
+        host_path: &PathBuf,
+        opts: &DeployOptions,
+        notes: &mut Vec<String>,
+    ) -> Result<DeployOutcome> {
+        // linking to a missing store file would only leave a dangling link
+        if !from_path.exists() {
+            if opts.strict {
+                return Err(anyhow!("{} is missing from the store", from_path.display()));
+            }
+            log::warn!(
+                "Not deploying {}: {} is missing from the store",
+                &entry.name,
+                from_path.display()
+            );
+            return Ok(DeployOutcome::Skipped("missing from store".to_owned()));
+        }
+        // without --strict the variable is kept as written in the path
+        let unset = unset_vars(&entry.host_path);
+        if opts.strict && !unset.is_empty() {
+            return Err(anyhow!("${} is not set", unset.join(", $")));
+        }
+        if let Some(reason) = self.host_path_problem(entry, host_path) {
+            return Ok(DeployOutcome::Conflict(reason));
+        }
+        return self
+            .place_entry(entry, from_path, host_path, opts, notes)
+            .with_context(|| format!("at {}", host_path.display()));
+    }
+
+    fn place_entry(
+        &self,
+        entry: &Entry,
+        from_path: &PathBuf,
+        host_path: &PathBuf,
+        opts: &DeployOptions,
+        notes: &mut Vec<String>,
+    ) -> Result<DeployOutcome> {
+        let (force, only_missing) = (opts.force, opts.only_missing);
+        if entry.encrypted {
+            if only_missing && host_path.exists() {
+                return Ok(DeployOutcome::AlreadyLinked("already decrypted"));
+            }
+            let plain = age_decrypt(from_path, &trove_key(&self.home)?)?;
+            // writing through a leftover link would land in its target
+            if std::fs::read_link(host_path).is_ok() {
+                symlink::remove_symlink_auto(host_path)?;
+            }
+            if let Some(parent) = host_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(host_path, plain)?;
+            apply_mode(host_path, entry.mode)?;
+            log::info!("Decrypted {} to {}", &entry.name, host_path.display());
+            return Ok(DeployOutcome::Created);
+        }
+        if entry.is_copy() {
+            if only_missing && host_path.exists() {
+                return Ok(DeployOutcome::AlreadyLinked("already copied"));
+            }
+            // copied entries are written over the host directory rather than linked
+            copy_tree(from_path, host_path, &[])?;
+            apply_mode(host_path, entry.mode)?;
+            log::info!("Copied {} to {}", &entry.name, host_path.display());
+            return Ok(DeployOutcome::Created);
+        }
+        let current = std::fs::read_link(host_path).ok();
+        if current.as_ref() == Some(from_path) {
+            return Ok(DeployOutcome::AlreadyLinked("already linked"));
+        }
+        if let (true, false, Some(old)) = (only_missing, force, &current) {
+            // a link to the wrong place is replaced, real files are left to --force
+            symlink::remove_symlink_auto(host_path)?;
+            notes.push(format!(
+                "Relinked {} (was {})",
+                host_path.display(),
+                old.display()
+            ));
+        } else if force {
+            if clear_host_path(host_path, &|dir| opts.confirm_overwrite(dir))? {
+                notes.push(format!("Overwrote {}", host_path.display()));
+            }
+        } else if std::fs::symlink_metadata(host_path).is_ok() {
+            return Ok(DeployOutcome::Conflict(format!(
+                "{} already exists",
+                host_path.display()
+            )));
+        }
+        // fresh machines may not have the parent directories yet
+        if let Some(parent) = host_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        link_path(from_path, host_path, self.config.dir_links)?;
+        log::info!("Deployed {} to {}", &entry.name, host_path.display());
+        return Ok(DeployOutcome::Created);
+    }
+
+    pub fn pack_entry(&self, entry: &Entry, target_dir: &Option<PathBuf>) -> Result<PackOutcome> {
+        // takes one entry's link or plaintext away, Err when it should have been packed but couldn't be
+        let host_path = self.deploy_path(entry, target_dir);
+        if let Some(reason) = self.host_path_problem(entry, &host_path) {
+            log::warn!("Not packing {}: {}", &entry.name, reason);
+            return Ok(PackOutcome::Skipped("unusable host path"));
+        }
+        if entry.is_copy() {
+            return Ok(PackOutcome::Skipped("copied, not linked"));
+        }
+        if entry.encrypted {
+            if !host_path.is_file() {
+                return Ok(PackOutcome::Skipped("not deployed"));
+            }
+            // edits made since deploying would be lost with the plaintext
+            let plain = age_decrypt(&self.stored_path(entry), &trove_key(&self.home)?)?;
+            if std::fs::read(&host_path)? != plain {
+                return Err(anyhow!(
+                    "{} has changes that are not in the store.",
+                    host_path.display()
+                ));
+            }
+            std::fs::remove_file(&host_path)?;
+            log::info!("Packed {} from {}", &entry.name, host_path.display());
+            return Ok(PackOutcome::Packed);
+        }
+        match std::fs::symlink_metadata(&host_path) {
+            Ok(meta) => {
+                if !meta.file_type().is_symlink() {
+                    return Err(anyhow!("{} is not a symlink.", host_path.display()));
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(PackOutcome::Skipped("not deployed"));
+            }
+            Err(err) => return Err(err.into()),
+        }
+        symlink::remove_symlink_auto(&host_path)?;
+        log::info!("Packed {} from {}", &entry.name, host_path.display());
+        return Ok(PackOutcome::Packed);
+    }
+
+    pub fn deploy_command(
+        &mut self,
+        category: &Option<String>,
+        name: &Option<String>,
+        mode: CategoryMatch,
+        opts: &DeployOptions,
+    ) -> Result<Report> {
+        // a single named entry fails the command outright, bulk failures go in the report
+        let mut report = Report::default();
+        let entries = self.select_entries(category, name, mode, &opts.exclude)?;
+        let externalized: Vec<bool> = entries
+            .iter()
+            .map(|e| opts.target_dir.is_none() && self.is_externalized(e))
+            .collect();
+        let results = self.deploy_entries(&entries, opts);
+        let mut readopted = Vec::new();
+        for ((e, externalized), (deployed, notes)) in entries.iter().zip(externalized).zip(results)
+        {
+            report.notes.extend(notes);
+            match deployed {
+                Ok(DeployOutcome::Created) => {
+                    if externalized {
+                        readopted.push(e.clone());
+                    }
+                    report.done.push(e.name.clone());
+                }
+                Ok(DeployOutcome::AlreadyLinked(reason)) => report.skip(&e.name, reason),
+                Ok(DeployOutcome::Skipped(reason)) => report.skip(&e.name, &reason),
+                // an unusable host path fails only this entry, the rest still deploy
+                Ok(DeployOutcome::Conflict(reason)) if name.is_some() => {
+                    return Err(anyhow!("Could not deploy {}: {}", &e.name, reason))
+                }
+                Ok(DeployOutcome::Conflict(reason)) => {
+                    report.fail("deploy", &e.name, anyhow!(reason))
+                }
+                Err(err) if name.is_some() => {
+                    return Err(err.context(format!("Could not deploy {}", &e.name)))
+                }
+                Err(err) => report.fail("deploy", &e.name, err),
+            }
+        }
+        if !readopted.is_empty() {
+            for mut e in readopted {
+                e.externalized = false;
+                self.entries.replace(e);
+            }
+            self.save()?;
+        }
+        return Ok(report);
+    }
+
+    fn deploy_entries(
+        &self,
+        entries: &[Entry],
+        opts: &DeployOptions,
+    ) -> Vec<(Result<DeployOutcome>, Vec<String>)> {
+        // each entry's outcome and notes, in the order given however many jobs ran
+        let deploy = |e: &Entry, opts: &DeployOptions| {
+            let mut notes = Vec::new();
+            let deployed = self.deploy_entry(e, opts, &mut notes);
+            return (deployed, notes);
+        };
+        if opts.jobs < 2 || entries.len() < 2 {
+            return entries.iter().map(|e| deploy(e, opts)).collect();
+        }
+        // entries don't share host paths, only the prompt has to be taken in turns
+        let prompt = Mutex::new(());
+        let overwrite = |path: &PathBuf| {
+            let _turn = prompt.lock().unwrap_or_else(|e| e.into_inner());
+            return opts.confirm_overwrite(path);
+        };
+        let opts = DeployOptions {
+            overwrite_dir: Some(&overwrite),
+            ..opts.clone()
+        };
+        let next = AtomicUsize::new(0);
+        let done = Mutex::new(Vec::with_capacity(entries.len()));
+        std::thread::scope(|scope| {
+            for _ in 0..opts.jobs.min(entries.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let e = match entries.get(i) {
+                        Some(e) => e,
+                        None => break,
+                    };
+                    let result = deploy(e, &opts);
+                    done.lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push((i, result));
+                });
+            }
+        });
+        let mut done = done.into_inner().unwrap_or_else(|e| e.into_inner());
+        done.sort_by_key(|(i, _)| *i);
+        return done.into_iter().map(|(_, result)| result).collect();
+    }
+
+    pub fn changed_since(&self, git_ref: &str) -> Result<Option<Vec<String>>> {
+        // names of entries whose stored files differ between git_ref and HEAD,
+        // None when the store isn't in a git work tree to ask
+        let store = expand_home(&self.config.store_path, &self.home);
+        let in_repo = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&store)
+            .args(["rev-parse", "--is-inside-work-tree"])
+            .output();
+        match in_repo {
+            Ok(out) if out.status.success() => {}
+            Ok(_) => {
+                log::warn!("{} is not in a git repository", store.display());
+                return Ok(None);
+            }
+            Err(err) => {
+                log::warn!("Could not run git: {}", err);
+                return Ok(None);
+            }
+        }
+        let out = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&store)
+            .args(["diff", "--name-only", "--relative", git_ref, "HEAD", "--"])
+            .output()
+            .context("Could not run git")?;
+        if !out.status.success() {
+            return Err(anyhow!(
+                "git diff against {} failed: {}",
+                git_ref,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ));
+        }
+        let changed = String::from_utf8_lossy(&out.stdout).to_string();
+        let mut names: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|e| {
+                // a directory entry changed if anything under it did
+                let stored = e.stored_name();
+                changed.lines().any(|p| {
+                    p == stored || p.strip_prefix(stored).is_some_and(|r| r.starts_with('/'))
+                })
+            })
+            .map(|e| e.name.clone())
+            .collect();
+        names.sort();
+        return Ok(Some(names));
+    }
+
+    pub fn host_path_problem(&self, entry: &Entry, host_path: &Path) -> Option<String> {
+        // why the host path can't hold a link at all, if it can't
+        if !host_path.is_absolute() {
+            return Some(format!(
+                "{} does not resolve to an absolute path",
+                &entry.host_path
+            ));
+        }
+        let existing = host_path
+            .ancestors()
+            .skip(1)
+            .find(|p| std::fs::symlink_metadata(p).is_ok())?;
+        if !existing.is_dir() {
+            return Some(format!("{} is not a directory", existing.display()));
+        }
+        return None;
+    }
+
+    pub fn pack_command(
+        &self,
+        category: &Option<String>,
+        name: &Option<String>,
+        mode: CategoryMatch,
+        opts: &PackOptions,
+    ) -> Result<Report> {
+        let mut report = Report::default();
+        let entries = self.select_entries(category, name, mode, &opts.exclude)?;
+        for e in &entries {
+            match self.pack_entry(e, &opts.target_dir) {
+                Ok(PackOutcome::Packed) => report.done.push(e.name.clone()),
+                Ok(PackOutcome::Skipped(reason)) => report.skip(&e.name, reason),
+                Err(err) if name.is_some() => {
+                    return Err(err.context(format!("Could not pack {}", &e.name)))
+                }
+                Err(err) => report.fail("pack", &e.name, err),
+            }
+        }
+        return Ok(report);
+    }
+
+    pub fn pack_restore_command(
+        &mut self,
+        category: &Option<String>,
+        name: &Option<String>,
+        mode: CategoryMatch,
+        exclude: &Exclude,
+    ) -> Result<Report> {
+        // like remove, the stored file goes back to the host path, but the entry is kept
+        let mut report = Report::default();
+        let entries = self.select_entries(category, name, mode, exclude)?;
+        for e in &entries {
+            let restored = self.externalize_entry(e);
+            match restored {
+                Ok(None) => report.done.push(e.name.clone()),
+                Ok(Some(reason)) => report.skip(&e.name, reason),
+                Err(err) if name.is_some() => {
+                    return Err(err.context(format!("Could not pack {}", &e.name)))
+                }
+                Err(err) => report.fail("pack", &e.name, err),
+            }
+        }
+        return Ok(report);
+    }
+
+    fn externalize_entry(&mut self, entry: &Entry) -> Result<Option<&'static str>> {
+        // Some(reason) when there is nothing to move back
+        if !entry.is_linked() {
+            return Ok(Some("not linked"));
+        }
+        if self.is_externalized(entry) {
+            return Ok(Some("already externalized"));
+        }
+        let stored = self.stored_path(entry);
+        if !stored.exists() {
+            return Ok(Some("missing from store"));
+        }
+        let linked = match self.pack_entry(entry, &None)? {
+            PackOutcome::Packed => true,
+            PackOutcome::Skipped("not deployed") => false,
+            PackOutcome::Skipped(reason) => return Ok(Some(reason)),
+        };
+        let host_path = self.deploy_path(entry, &None);
+        if let Some(parent) = host_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if let Err(err) = move_path(&stored, &host_path) {
+            if linked {
+                link_path(&stored, &host_path, self.config.dir_links)?;
+            }
+            return Err(err);
+        }
+        let mut entry = entry.clone();
+        entry.externalized = true;
+        entry.discovered = false;
+        log::info!("Moved {} back to {}", &entry.name, host_path.display());
+        self.entries.replace(entry);
+        self.save()?;
+        return Ok(None);
+    }
+
+    pub fn select_entries(
+        &self,
+        category: &Option<String>,
+        name: &Option<String>,
+        mode: CategoryMatch,
+        exclude: &Exclude,
+    ) -> Result<Vec<Entry>> {
+        let mut selected: Vec<Entry> = match (category, name) {
+            (None, None) => self.entries_sorted().into_iter().cloned().collect(),
+            (None, Some(n)) => match self.find_entry_by_name(n) {
+                Some(e) => vec![e],
+                None => return Err(TroveError::NotFound(n.clone()).into()),
+            },
+            (Some(c), None) => match self.find_entry_by_category(c, mode) {
+                Some(entries) => entries.into_iter().collect(),
+                None => return Err(TroveError::NoEntries(c.clone()).into()),
+            },
+            // the CLI rejects this at parse time, library callers land here
+            (Some(_), Some(_)) => return Err(TroveError::AmbiguousCriteria.into()),
+        };
+        // exclusions narrow whatever was selected, unknown ones are only worth a warning
+        let mut names = Vec::new();
+        for n in &exclude.names {
+            match self.find_entry_by_name(n) {
+                Some(e) => names.push(e.name),
+                None => log::warn!("Excluded entry {} does not exist", n),
+            }
+        }
+        let categories = &exclude.categories;
+        for c in categories {
+            if !self.entries.iter().any(|e| e.categories.contains(c)) {
+                log::warn!("Excluded category {} has no entries", c);
+            }
+        }
+        selected.retain(|e| {
+            !names.contains(&e.name) && !e.categories.iter().any(|c| categories.contains(c))
+        });
+        selected.sort_by(|a, b| a.name.cmp(&b.name));
+        return Ok(selected);
+    }
+
+    fn unlink_stale(&self, entry: &Entry, report: &mut Report) -> Result<()> {
+        // links left at host paths the journal recorded before the entry moved
+        let stored = self.stored_path(entry);
+        for r in self.read_history()? {
+            if r.entry.name != entry.name || r.host_path == entry.host_path {
+                continue;
+            }
+            let old = expand_home(&r.host_path, &self.home);
+            match std::fs::read_link(&old) {
+                Ok(target) if target == stored => {
+                    symlink::remove_symlink_auto(&old)?;
+                    report
+                        .notes
+                        .push(format!("Removed stale link at {}", old.display()));
+                }
+                _ => {}
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn clean_links(&self, target_dir: &Option<PathBuf>) -> Result<Report> {
+        // removes dangling links into the store at places no current entry deploys to,
+        // looking at journaled host paths and next to current host paths
+        let mut report = Report::default();
+        let store = expand_home(&self.config.store_path, &self.home);
+        let stores = [
+            std::fs::canonicalize(&store).unwrap_or(store.clone()),
+            store,
+        ];
+        let current: Vec<PathBuf> = self
+            .entries
+            .iter()
+            .map(|e| normalize_path(&self.deploy_path(e, target_dir)))
+            .collect();
+        let mut candidates: Vec<PathBuf> = Vec::new();
+        for r in self.read_history()? {
+            let old = Entry {
+                host_path: r.host_path.clone(),
+                ..r.entry
+            };
+            candidates.push(self.deploy_path(&old, target_dir));
+        }
+        for path in &current {
+            let siblings = path.parent().and_then(|p| std::fs::read_dir(p).ok());
+            for item in siblings.into_iter().flatten().flatten() {
+                candidates.push(item.path());
+            }
+        }
+        candidates.sort();
+        candidates.dedup();
+        let conf_link = home_trove_link(&self.home).ok();
+        for link in candidates {
+            if current.contains(&normalize_path(&link)) || conf_link.as_ref() == Some(&link) {
+                continue;
+            }
+            let mut target = match std::fs::read_link(&link) {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+            if target.is_relative() {
+                if let Some(parent) = link.parent() {
+                    target = normalize_path(&parent.join(target));
+                }
+            }
+            // a link to a stored file that still exists may be the user's own
+            if !stores.iter().any(|s| target.starts_with(s))
+                || std::fs::symlink_metadata(&target).is_ok()
+            {
+                continue;
+            }
+            match symlink::remove_symlink_auto(&link) {
+                Ok(_) => {
+                    report.done.push(link.display().to_string());
+                    report
+                        .notes
+                        .push(format!("Removed stale link at {}", link.display()));
+                }
+                Err(err) => report.fail("clean", &link.display().to_string(), err.into()),
+            }
+        }
+        return Ok(report);
+    }
+
+    pub fn redeploy_command(
+        &mut self,
+        category: &Option<String>,
+        name: &Option<String>,
+        mode: CategoryMatch,
+    ) -> Result<Report> {
+        let none = Exclude::default();
+        let entries = self.select_entries(category, name, mode, &none)?;
+        let mut report = self.pack_command(category, name, mode, &PackOptions::default())?;
+        report.check("pack")?;
+        // only the deploy half is worth summarizing
+        report.done.clear();
+        report.skipped.clear();
+        for e in &entries {
+            self.unlink_stale(e, &mut report)?;
+        }
+        let deployed = self.deploy_command(category, name, mode, &DeployOptions::default())?;
+        report.merge(deployed);
+        return Ok(report);
+    }
+
+    fn verify_entry(&self, entry: &Entry, store: &PathBuf, follow_symlinks: bool) -> Result<()> {
+        let stored = self.stored_path(entry);
+        if self.is_externalized(entry) {
+            // pack --restore left the real file at the host path on purpose
+            return Ok(());
+        }
+        if !stored.exists() {
+            return Err(anyhow!("missing from store"));
+        }
+        if !entry.is_linked() {
+            return Ok(());
+        }
+        let host_path = expand_home(&entry.host_path, &self.home);
+        match std::fs::symlink_metadata(&host_path) {
+            Ok(meta) if !meta.file_type().is_symlink() => return Err(anyhow!("not a symlink")),
+            Ok(_) => {}
+            Err(_) => {
+                log::info!("{} is not deployed", &entry.name);
+                return Ok(());
+            }
+        }
+        // relative link targets are relative to the link's own directory
+        let mut target = std::fs::read_link(&host_path)?;
+        if target.is_relative() {
+            if let Some(parent) = host_path.parent() {
+                target = parent.join(target);
+            }
+        }
+        if !target.exists() {
+            return Err(anyhow!("dangling link to {}", target.display()));
+        }
+        // lexically a store reached through a linked directory still contains its links
+        let (target, stored) = if follow_symlinks {
+            (
+                std::fs::canonicalize(&target)?,
+                std::fs::canonicalize(&stored)?,
+            )
+        } else {
+            (normalize_path(&target), normalize_path(&stored))
+        };
+        if !target.starts_with(store) {
+            return Err(anyhow!("points outside store, at {}", target.display()));
+        }
+        if target != stored {
+            return Err(anyhow!("points at {}", target.display()));
+        }
+        return Ok(());
+    }
+
+    pub fn search_command(&self, query: &str, fuzzy: bool) -> Vec<Entry> {
+        // best match first, a hit on the name outranks categories, then the host path
+        let query = query.to_lowercase();
+        let mut found: Vec<(u32, Entry)> = Vec::new();
+        for e in &self.entries {
+            let fields = [
+                (3, vec![e.name.clone()]),
+                (2, e.categories.clone()),
+                (1, vec![e.host_path.clone()]),
+            ];
+            let mut best = None;
+            for (weight, texts) in fields {
+                for text in texts {
+                    let text = text.to_lowercase();
+                    let score = if fuzzy {
+                        fuzzy_score(&query, &text)
+                    } else {
+                        substring_score(&query, &text)
+                    };
+                    if let Some(score) = score {
+                        best = best.max(Some(weight * 1000 + score));
+                    }
+                }
+            }
+            if let Some(score) = best {
+                found.push((score, e.clone()));
+            }
+        }
+        found.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name)));
+        return found.into_iter().map(|(_, e)| e).collect();
+    }
+
+    pub fn describe_command(&mut self, name: &str, description: Option<String>) -> Result<Entry> {
+        // sets or clears an entry's description
+        let mut entry = match self.find_entry_by_name(name) {
+            Some(e) => e,
+            None => return Err(TroveError::NotFound(name.into()).into()),
+        };
+        entry.description = description;
+        // a described entry is worth keeping, so a discovered one becomes explicit
+        entry.discovered = false;
+        self.entries.replace(entry.clone());
+        self.save()?;
+        return Ok(entry);
+    }
+
+    pub fn rename_category_command(&mut self, old: &str, new: &str) -> Result<Vec<String>> {
+        // retags every entry carrying old, returning their names
+        let tagged: Vec<Entry> = self
+            .entries
+            .iter()
+            .filter(|e| e.categories.iter().any(|c| c == old))
+            .cloned()
+            .collect();
+        if tagged.is_empty() {
+            return Err(TroveError::NoEntries(old.into()).into());
+        }
+        let mut renamed = Vec::new();
+        for mut e in tagged {
+            e.categories = rename_category(&e.categories, old, new);
+            renamed.push(e.name.clone());
+            self.entries.replace(e);
+        }
+        self.config.default_categories = rename_category(&self.config.default_categories, old, new);
+        self.save()?;
+        renamed.sort();
+        return Ok(renamed);
+    }
+
+    pub fn post_link_command(&mut self, name: &str, command: Option<String>) -> Result<Entry> {
+        // sets or clears the command deploy runs after linking an entry
+        let mut entry = match self.find_entry_by_name(name) {
+            Some(e) => e,
+            None => return Err(TroveError::NotFound(name.into()).into()),
+        };
+        entry.post_link = command.filter(|c| !c.trim().is_empty());
+        entry.discovered = false;
+        self.entries.replace(entry.clone());
+        self.save()?;
+        return Ok(entry);
+    }
+
+    pub fn move_store_command(&mut self, new_path: &PathBuf) -> Result<Report> {
+        let old = get_absolute_path(&expand_home(&self.config.store_path, &self.home))?;
+        let new = std::env::current_dir()?.join(new_path);
+        if new.exists() {
+            return Err(TroveError::DuplicatePath(new).into());
+        }
+        // old is canonical, new has to be too before comparing
+        if canonicalize_missing(&new).starts_with(&old) {
+            return Err(anyhow!("Cannot move the store inside itself."));
+        }
+        // note which entries are linked into the store before anything moves
+        let mut deployed = Vec::new();
+        for e in self.entries_sorted() {
+            let host_path = expand_home(&e.host_path, &self.home);
+            if let Ok(target) = std::fs::read_link(&host_path) {
+                if std::fs::canonicalize(&host_path).ok() == Some(old.join(e.stored_name())) {
+                    deployed.push((e.clone(), host_path, target));
+                }
+            }
+        }
+        if let Some(parent) = new.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        move_path(&old, &new)?;
+        self.config.store_path = contract_home(&new, &self.home);
+        self.save()?;
+
+        let mut report = Report::default();
+        report
+            .notes
+            .push(format!("Moved store to {}", new.display()));
+        for (e, host_path, target) in deployed {
+            // relative links that still resolve survive the move
+            if target.is_relative() && host_path.exists() {
+                continue;
+            }
+            let relinked = symlink::remove_symlink_auto(&host_path)
+                .and_then(|_| link_path(&self.stored_path(&e), &host_path, self.config.dir_links));
+            match relinked {
+                Ok(_) => report.done.push(e.name.clone()),
+                Err(err) => report.fail("relink", &e.name, err.into()),
+            }
+        }
+        return Ok(report);
+    }
+
+    pub fn store_ignore(&self) -> Result<Vec<String>> {
+        // glob patterns for store files that aren't entries, one per line
+        let path = expand_home(&self.config.store_path, &self.home).join(".troveignore");
+        let cont = match std::fs::read_to_string(&path) {
+            Ok(cont) => cont,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Could not read {}", path.display()))
+            }
+        };
+        let mut patterns = Vec::new();
+        for line in cont.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // glob_match would take these literally and never match, so say so instead
+            match unsupported_store_glob(line) {
+                Some(reason) => log::warn!("Skipping {} in {}: {}", line, path.display(), reason),
+                None => patterns.push(line.to_string()),
+            }
+        }
+        return Ok(patterns);
+    }
+
+    fn untracked_store_items(&self) -> Result<Vec<std::fs::DirEntry>> {
+        // top level store items no entry refers to, by name, minus trove's own files
+        let store = expand_home(&self.config.store_path, &self.home);
+        let conf = expand_home(&self.config.path, &self.home);
+        let ignore_file = store.join(".troveignore");
+        let ignore = self.store_ignore()?;
+        let keep = [conf, self.history_path(), ignore_file];
+        let mut items: Vec<std::fs::DirEntry> =
+            std::fs::read_dir(&store)?.collect::<std::io::Result<_>>()?;
+        items.sort_by_key(|i| i.file_name());
+        items.retain(|item| {
+            let path = item.path();
+            let name = item.file_name().to_string_lossy().to_string();
+            // entries tracked in place can live in a subdirectory of the store
+            let tracked = |e: &Entry| {
+                let stored = e.stored_name();
+                stored == name || stored.starts_with(&format!("{}/", name))
+            };
+            if keep.contains(&path) || self.entries.iter().any(tracked) {
+                return false;
+            }
+            if is_ignored(&PathBuf::from(&name), &ignore) {
+                log::debug!("{} is listed in .troveignore", path.display());
+                return false;
+            }
+            return true;
+        });
+        return Ok(items);
+    }
+
+    fn discover_entries(&mut self) -> Result<()> {
+        // explicit entries win, by name or by where they deploy to
+        for item in self.untracked_store_items()? {
+            let name = item.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let entry = Entry {
+                name: name.clone(),
+                host_path: format!("$HOME/.{}", name),
+                categories: Vec::new(),
+                ignore: Vec::new(),
+                mode: None,
+                description: None,
+                added_at: None,
+                origin: None,
+                encrypted: false,
+                store_name: None,
+                content_hash: None,
+                post_link: None,
+                externalized: false,
+                discovered: true,
+            };
+            let dest = self.destination(&entry);
+            if let Some(e) = self.entries.iter().find(|e| self.destination(e) == dest) {
+                log::debug!("Not discovering {}, {} deploys there", &name, &e.name);
+                continue;
+            }
+            log::debug!("Discovered {} in the store", &name);
+            self.entries.insert(entry);
+        }
+        return Ok(());
+    }
+
+    pub fn prune_command(&self, delete: bool) -> Result<Report> {
+        // store files no entry refers to, only removed when asked
+        let mut report = Report::default();
+        for item in self.untracked_store_items()? {
+            let path = item.path();
+            let name = item.file_name().to_string_lossy().to_string();
+            if !delete {
+                report
+                    .notes
+                    .push(format!("{} is not tracked by any entry", path.display()));
+                report.skip(&name, "not deleted");
+                continue;
+            }
+            let removed = if item.file_type()?.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            match removed {
+                Ok(_) => {
+                    report.notes.push(format!("Removed {}", path.display()));
+                    report.done.push(name);
+                }
+                Err(err) => report.fail("prune", &name, err.into()),
+            }
+        }
+        return Ok(report);
+    }
+
+    pub fn is_deployed(&self, entry: &Entry) -> bool {
+        // linked to its own stored file, or for copies, written out at all
+        let host_path = self.deploy_path(entry, &None);
+        if !entry.is_linked() {
+            return host_path.exists();
+        }
+        let stored = self.stored_path(entry);
+        return stored.exists() && std::fs::read_link(&host_path).ok() == Some(stored);
+    }
+
+    pub fn category_counts(&self) -> Vec<(String, usize)> {
+        // how many entries are in each category, by category name
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        for e in &self.entries {
+            for c in &e.categories {
+                match counts.iter_mut().find(|(name, _)| name == c) {
+                    Some((_, n)) => *n += 1,
+                    None => counts.push((c.clone(), 1)),
+                }
+            }
+        }
+        counts.sort();
+        return counts;
+    }
+
+    pub fn status_summary(&self) -> StatusSummary {
+        let deployed = self.entries.iter().filter(|e| self.is_deployed(e)).count();
+        let categories = self
+            .category_counts()
+            .into_iter()
+            .map(|(name, entries)| CategoryCount { name, entries })
+            .collect();
+        return StatusSummary {
+            config: expand_home(&self.config.path, &self.home),
+            store: expand_home(&self.config.store_path, &self.home),
+            entries: self.entries.len(),
+            deployed,
+            broken: self.broken_entries().len(),
+            categories,
+        };
+    }
+
+    pub fn stats_command(&self, largest: usize) -> Stats {
+        // sizes come from the entries' store paths, missing ones count as empty
+        let summary = self.status_summary();
+        let mut sizes = Vec::new();
+        let mut files = 0;
+        for e in &self.entries {
+            let (bytes, count) = disk_usage(&self.stored_path(e));
+            files += count;
+            sizes.push(EntrySize {
+                name: e.name.clone(),
+                bytes,
+            });
+        }
+        sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+        let store_bytes = sizes.iter().map(|s| s.bytes).sum();
+        sizes.truncate(largest);
+        return Stats {
+            entries: summary.entries,
+            deployed: summary.deployed,
+            categories: summary.categories,
+            store_bytes,
+            files,
+            largest: sizes,
+        };
+    }
+
+    pub fn entry_listing(&self, entry: &Entry, store_relative: bool) -> EntryListing {
+        return EntryListing {
+            name: entry.name.clone(),
+            store_path: self.display_stored_path(entry, store_relative),
+            host_path: entry.host_path.clone(),
+            categories: entry.categories.clone(),
+            description: entry.description.clone(),
+            added_at: entry.added_at.clone(),
+            origin: entry.origin.clone(),
+            deployed: self.is_deployed(entry),
+        };
+    }
+
+    pub fn broken_entries(&self) -> Vec<(Entry, &'static str)> {
+        // entries that can't deploy cleanly, sorted by name; packed entries are fine
+        let entries = self.entries_sorted();
+        let mut broken = Vec::new();
+        for e in entries {
+            let host_path = expand_home(&e.host_path, &self.home);
+            let reason = match std::fs::symlink_metadata(&host_path) {
+                _ if self.is_externalized(e) => None,
+                _ if !self.stored_path(e).exists() => Some("missing from store"),
+                Ok(meta) if meta.file_type().is_symlink() && !host_path.exists() => {
+                    Some("dangling link")
+                }
+                Ok(meta) if !meta.file_type().is_symlink() && e.is_linked() => Some("not a link"),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                broken.push((e.clone(), reason));
+            }
+        }
+        return broken;
+    }
+
+    pub fn verify_command(&self, follow_symlinks: bool) -> Result<Report> {
+        // checks every deployed host path is a link to its own stored file, comparing
+        // fully resolved paths with follow_symlinks and only folding . and .. without
+        let store = expand_home(&self.config.store_path, &self.home);
+        let store = if follow_symlinks {
+            std::fs::canonicalize(store).context("Could not resolve the store directory")?
+        } else {
+            normalize_path(&store)
+        };
+        let mut report = Report::default();
+        for e in self.entries_sorted() {
+            match self.verify_entry(e, &store, follow_symlinks) {
+                Ok(_) => report.done.push(e.name.clone()),
+                Err(err) => report.failed.push((e.name.clone(), err.to_string())),
+            }
+        }
+        for (a, b) in self.find_destination_conflicts() {
+            report.done.retain(|n| n != &a);
+            report
+                .failed
+                .push((a, format!("deploys to the same host path as {}", b)));
+        }
+        return Ok(report);
+    }
+
+    pub fn check_drift_command(&mut self, update: bool) -> Result<Report> {
+        // compares copied and encrypted entries' host content with the hash recorded for it,
+        // update records the current content instead
+        let mut report = Report::default();
+        let entries: Vec<Entry> = self.entries_sorted().into_iter().cloned().collect();
+        for mut e in entries {
+            if e.is_linked() {
+                report.skip(&e.name, "linked (no drift possible)");
+                continue;
+            }
+            let host_path = self.deploy_path(&e, &None);
+            if std::fs::symlink_metadata(&host_path).is_err() {
+                report.skip(&e.name, "not deployed");
+                continue;
+            }
+            let hash = match content_hash(&host_path, &e.ignore) {
+                Ok(h) => h,
+                Err(err) => {
+                    report.fail("hash", &e.name, err);
+                    continue;
+                }
+            };
+            if update {
+                e.content_hash = Some(hash);
+                report.done.push(e.name.clone());
+                self.entries.replace(e);
+                continue;
+            }
+            match &e.content_hash {
+                None => report.skip(&e.name, "no hash recorded"),
+                Some(recorded) if recorded == &hash => report.done.push(e.name.clone()),
+                Some(_) => report.failed.push((
+                    e.name.clone(),
+                    format!("drifted at {}", host_path.display()),
+                )),
+            }
+        }
+        if update {
+            self.save()?;
+        }
+        return Ok(report);
+    }
+
+    pub fn doctor_command(
+        &mut self,
+        conf: &PathBuf,
+        remove_link: &dyn Fn(&Entry, &PathBuf) -> Result<bool>,
+    ) -> Result<Report> {
+        // fixes what verify complains about where that is safe, done holds the fixes
+        // and failed what is still wrong
+        let mut report = Report::default();
+        let link = home_trove_link(&self.home)?;
+        if let Some(old) = stale_link_target(&link) {
+            self.repair_conf_symlink(conf)?;
+            report.done.push(link.display().to_string());
+            report.notes.push(format!(
+                "Re-pointed {} (was {})",
+                link.display(),
+                old.display()
+            ));
+        }
+        let entries: Vec<Entry> = self.entries_sorted().into_iter().cloned().collect();
+        for e in &entries {
+            if self.is_externalized(e) {
+                continue;
+            }
+            let host_path = self.deploy_path(e, &None);
+            let meta = std::fs::symlink_metadata(&host_path).ok();
+            let is_link = meta.as_ref().is_some_and(|m| m.file_type().is_symlink());
+            if !self.stored_path(e).exists() {
+                // nothing to relink to, only a dangling link can be cleaned up
+                if is_link && !host_path.exists() && remove_link(e, &host_path)? {
+                    symlink::remove_symlink_auto(&host_path)?;
+                    report.done.push(e.name.clone());
+                    report
+                        .notes
+                        .push(format!("Removed dangling link {}", host_path.display()));
+                } else {
+                    report
+                        .failed
+                        .push((e.name.clone(), "missing from store".to_owned()));
+                }
+                continue;
+            }
+            if meta.is_some() && !is_link && e.is_linked() {
+                report
+                    .failed
+                    .push((e.name.clone(), "not a symlink".to_owned()));
+                continue;
+            }
+            if self.is_deployed(e) {
+                continue;
+            }
+            let notes = report.notes.len();
+            let opts = DeployOptions {
+                only_missing: true,
+                ..DeployOptions::default()
+            };
+            match self.deploy_entry(e, &opts, &mut report.notes) {
+                Ok(DeployOutcome::Created) | Ok(DeployOutcome::AlreadyLinked(_)) => {
+                    report.done.push(e.name.clone());
+                    if report.notes.len() == notes {
+                        report
+                            .notes
+                            .push(format!("Linked {} to {}", &e.name, host_path.display()));
+                    }
+                }
+                Ok(DeployOutcome::Conflict(reason)) | Ok(DeployOutcome::Skipped(reason)) => {
+                    report.failed.push((e.name.clone(), reason))
+                }
+                Err(err) => report.failed.push((e.name.clone(), err.to_string())),
+            }
+        }
+        for (a, b) in self.find_destination_conflicts() {
+            report
+                .failed
+                .push((a, format!("deploys to the same host path as {}", b)));
+        }
+        return Ok(report);
+    }
+
+    fn restore_entry(&mut self, e: &Entry) -> Result<()> {
+        // move the stored file back to the host path, then drop the entry,
+        // so a failed move leaves the entry tracked and deployed
+        let from_path = self.stored_path(e);
+        let host_path = expand_home(&e.host_path, &self.home);
+        if e.is_copy() {
+            // write the stored copy back over the host directory
+            copy_tree(&from_path, &host_path, &[])?;
+            std::fs::remove_dir_all(from_path)?;
+            return self.remove_entry(e);
+        }
+        if e.encrypted {
+            // the host path keeps its plaintext, or gets it back if packed
+            if !host_path.exists() {
+                let plain = age_decrypt(&from_path, &trove_key(&self.home)?)?;
+                std::fs::write(&host_path, plain)?;
+                apply_mode(&host_path, e.mode)?;
+            }
+            std::fs::remove_file(from_path)?;
+            return self.remove_entry(e);
+        }
+        let linked = std::fs::read_link(&host_path).ok() == Some(from_path.clone());
+        if let Err(_) = symlink::remove_symlink_auto(&host_path) {
+            log::info!("Symlink does not exists, continuing...");
+        }
+        if let Err(err) = move_path(&from_path, &host_path) {
+            if linked {
+                symlink::symlink_auto(&from_path, &host_path)?;
+            }
+            return Err(err);
+        }
+        return self.remove_entry(e);
+    }
+
+    pub fn removal_targets(
+        &self,
+        path: &Option<PathBuf>,
+        name: &Option<String>,
+        category: &Option<String>,
+    ) -> Result<Option<Vec<Entry>>> {
+        // None means every entry
+        let found = match (path, name, category) {
+            (None, None, None) => return Ok(None),
+            (None, Some(n), None) => self.find_entry_by_name(n),
+            (Some(p), None, None) => {
+                let p = &expand_tilde(p, &self.home);
+                // the host path may not exist if the entry is packed
+                let abs = match get_absolute_path(p) {
+                    Ok(abs) => abs,
+                    Err(_) => std::env::current_dir()?.join(p),
+                };
+                self.find_entry_by_path(&abs)
+            }
+            (None, None, Some(c)) => match self.find_entry_by_category(c, CategoryMatch::Any) {
+                Some(entries) => {
+                    let mut entries: Vec<Entry> = entries.into_iter().collect();
+                    entries.sort_by(|a, b| a.name.cmp(&b.name));
+                    return Ok(Some(entries));
+                }
+                None => return Err(TroveError::NoEntries(c.clone()).into()),
+            },
+            _ => return Err(TroveError::AmbiguousCriteria.into()),
+        };
+        match found {
+            Some(e) => return Ok(Some(vec![e])),
+            None => {
+                let wanted = match (path, name) {
+                    (Some(p), _) => p.display().to_string(),
+                    (_, n) => n.clone().unwrap_or_default(),
+                };
+                return Err(TroveError::NotFound(wanted).into());
+            }
+        }
+    }
+
+    pub fn remove_command(
+        &mut self,
+        path: &Option<PathBuf>,
+        name: &Option<String>,
+        category: &Option<String>,
+    ) -> Result<Report> {
+        // a single entry fails the command outright, bulk failures go in the report
+        let mut report = Report::default();
+        let entries = match self.removal_targets(path, name, category)? {
+            Some(entries) => entries,
+            None if self.entries.is_empty() => {
+                return Err(anyhow!("Need criteria to remove by."));
+            }
+            None => self.entries_sorted().into_iter().cloned().collect(),
+        };
+        if path.is_some() || name.is_some() {
+            self.restore_entry(&entries[0])?;
+            self.record(Operation::Remove, &entries[0])?;
+            report.done.push(entries[0].name.clone());
+            return Ok(report);
+        }
+        for e in &entries {
+            match self.restore_entry(e) {
+                Ok(_) => {
+                    self.record(Operation::Remove, e)?;
+                    report.done.push(e.name.clone());
+                }
+                Err(err) => report.fail("remove", &e.name, err),
+            }
+        }
+        return Ok(report);
+    }
+}
+
+//util functions
+pub fn json_from_file(path: &PathBuf) -> Result<serde_json::Value> {
+    let file = std::fs::File::open(path)?;
+
+    let json = serde_json::from_reader(file)
+        .with_context(|| format!("{} is not valid JSON", path.display()))?;
+
+    return Ok(json);
+}
+
+pub fn json_to_file(path: &PathBuf, contents: &str) -> Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(contents.as_bytes())?;
+    return Ok(());
+}
+
+pub fn get_absolute_path(rel: &PathBuf) -> Result<PathBuf> {
+    // converts from relative path to absolute
+    let mut path = std::env::current_dir()?;
+    path.push(rel);
+    // this also Err if path doesn't exist
+    match std::fs::canonicalize(path) {
+        Ok(r) => return Ok(r),
+        Err(_) => return Err(TroveError::PathDoesNotExist(rel.clone()).into()),
+    }
+}
+
+pub fn migrate_config(json: &mut serde_json::Value) -> Result<bool> {
+    // upgrades a parsed trove.conf in place, true if anything changed
+    let version = json["config"]["version"].as_u64().unwrap_or(0) as u32;
+    if version > CONFIG_VERSION {
+        return Err(anyhow!(
+            "trove.conf is version {}, but this dot-trove only understands up to {}. Please upgrade.",
+            version,
+            CONFIG_VERSION
+        ));
+    }
+    if version == CONFIG_VERSION {
+        return Ok(false);
+    }
+    if version < 1 {
+        // v0 predates ignore patterns
+        let config = &mut json["config"];
+        if config.get("ignore").is_none() {
+            config["ignore"] = serde_json::json!([]);
+        }
+    }
+    json["config"]["version"] = serde_json::json!(CONFIG_VERSION);
+    return Ok(true);
+}
+
+pub fn get_absolute_path_no_follow(rel: &PathBuf) -> Result<PathBuf> {
+    // like get_absolute_path, but a symlink in the last component is kept as is
+    let mut path = std::env::current_dir()?;
+    path.push(rel);
+    let file_name = match path.file_name() {
+        Some(f) if rel.file_name().is_some() => f.to_owned(),
+        _ => return get_absolute_path(&path),
+    };
+    if std::fs::symlink_metadata(&path).is_err() {
+        return Err(TroveError::PathDoesNotExist(rel.clone()).into());
+    }
+    let parent = match path.parent() {
+        Some(p) => get_absolute_path(&p.to_path_buf())?,
+        None => return get_absolute_path(&path),
+    };
+    return Ok(parent.join(file_name));
+}
+
+pub fn normalize_path(path: &Path) -> PathBuf {
+    // drops trailing separators and `.`, and folds `..` into its parent unless that is a symlink
+    let mut out = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                let is_link = std::fs::symlink_metadata(&out)
+                    .map(|m| m.file_type().is_symlink())
+                    .unwrap_or(false);
+                match out.components().next_back() {
+                    Some(Component::Normal(_)) if !is_link => {
+                        out.pop();
+                    }
+                    Some(Component::RootDir) => {}
+                    _ => out.push(".."),
+                }
+            }
+            other => out.push(other),
+        }
+    }
+    return out;
+}
+
+fn canonicalize_missing(path: &Path) -> PathBuf {
+    // canonicalizes the part of a path that exists and appends the rest, normalized
+    let path = normalize_path(path);
+    for existing in path.ancestors() {
+        if let Ok(real) = std::fs::canonicalize(existing) {
+            let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+            return normalize_path(&real.join(rest));
+        }
+    }
+    return path;
+}
+
+pub fn expand_home(s: &str, home: &Home) -> PathBuf {
+    // converts a leading $HOME shorthand back to the full home directory,
+    // then any other $VAR from the environment
+    let mut out = PathBuf::from(expand_vars(s, &mut vec![]));
+    if let Some(dir) = home.dir() {
+        if s == "$HOME" {
+            out = dir;
+        } else if let Some(rest) = s.strip_prefix("$HOME/") {
+            out = dir.join(expand_vars(rest, &mut vec![]));
+        }
+    }
+    log::debug!("Resolved {} to {}", s, out.display());
+    return out;
+}
+
+pub fn expand_tilde(path: &Path, home: &Home) -> PathBuf {
+    // a leading ~ or ~/ as the shell would expand it, ~user is left alone
+    let rest = match path.strip_prefix("~") {
+        Ok(rest) => rest,
+        Err(_) => return path.to_path_buf(),
+    };
+    return match home.dir() {
+        Some(dir) if rest.as_os_str().is_empty() => dir,
+        Some(dir) => dir.join(rest),
+        None => path.to_path_buf(),
+    };
+}
+
+pub fn unset_vars(s: &str) -> Vec<String> {
+    // variables expand_home would have to leave as written
+    let mut missing = vec![];
+    expand_vars(s, &mut missing);
+    return missing;
+}
+
+fn expand_vars(s: &str, missing: &mut Vec<String>) -> String {
+    // $HOME is left alone, it belongs to Home and only counts as a leading prefix
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        let var = &after[..len];
+        match std::env::var(var) {
+            Ok(value) if !var.is_empty() && var != "HOME" => out.push_str(&value),
+            _ => {
+                if !var.is_empty() && var != "HOME" {
+                    missing.push(var.to_string());
+                }
+                out.push('$');
+                out.push_str(var);
+            }
+        }
+        rest = &after[len..];
+    }
+    out.push_str(rest);
+    return out;
+}
+
+pub fn derive_name(path: &PathBuf) -> Result<String> {
+    // entry name from the file name, so .bashrc is stored as bashrc
+    let file_name = match path.file_name() {
+        Some(f) => f.to_string_lossy().to_string(),
+        None => {
+            return Err(anyhow!(
+                "Could not derive a name from {}, please give one.",
+                path.display()
+            ))
+        }
+    };
+    let stripped = file_name.strip_prefix('.').unwrap_or(&file_name);
+    if stripped.is_empty() {
+        return Ok(file_name);
+    }
+    return Ok(stripped.to_owned());
+}
+
+pub fn shell_quote(s: &str) -> String {
+    // single quoted for sh, a quote inside becomes '\''
+    return format!("'{}'", s.replace('\'', "'\\''"));
+}
+
+pub fn fish_quote(s: &str) -> String {
+    // fish single quotes only treat \\ and \' specially
+    return format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"));
+}
+
+pub fn unix_now() -> Result<u64> {
+    return Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs());
+}
+
+pub fn rfc3339(secs: u64) -> String {
+    // seconds since the epoch as a UTC timestamp, e.g. 2024-03-01T12:00:00Z
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // civil date from days since 1970-01-01, after Howard Hinnant's days_from_civil
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    return format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    );
+}
+
+pub fn hostname() -> Option<String> {
+    // the kernel's idea of the host name, or the hostname command where there's no /proc
+    let name = match std::fs::read_to_string("/proc/sys/kernel/hostname") {
+        Ok(name) => name,
+        Err(_) => {
+            let out = std::process::Command::new("hostname").output().ok()?;
+            String::from_utf8(out.stdout).ok()?
+        }
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    return Some(name.to_owned());
+}
+
+pub fn merge_categories(repeated: &[String], comma: &Option<String>) -> Vec<String> {
+    // repeated flags are taken whole, the comma separated form is split
+    let mut out: Vec<String> = Vec::new();
+    let split = comma.as_deref().map(parse_categories).unwrap_or_default();
+    for c in repeated
+        .iter()
+        .map(|c| c.trim())
+        .chain(split.iter().map(|c| c.as_str()))
+    {
+        if !c.is_empty() && !out.iter().any(|x| x == c) {
+            out.push(c.to_owned());
+        }
+    }
+    return out;
+}
+
+pub fn parse_categories(s: &str) -> Vec<String> {
+    // split on commas, trimming and dropping empty or repeated categories
+    let mut out: Vec<String> = Vec::new();
+    for c in s.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+        if !out.iter().any(|x| x == c) {
+            out.push(c.to_owned());
+        }
+    }
+    return out;
+}
+
+pub fn group_by_category(entries: &[Entry]) -> Vec<(Option<String>, Vec<Entry>)> {
+    // each entry once, under its first category alphabetically, uncategorized ones last
+    let mut groups: Vec<(Option<String>, Vec<Entry>)> = Vec::new();
+    for e in entries {
+        let first = e.categories.iter().min().cloned();
+        match groups.iter_mut().find(|(c, _)| c == &first) {
+            Some((_, group)) => group.push(e.clone()),
+            None => groups.push((first, vec![e.clone()])),
+        }
+    }
+    groups.sort_by(|a, b| (a.0.is_none(), &a.0).cmp(&(b.0.is_none(), &b.0)));
+    for (_, group) in &mut groups {
+        group.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    return groups;
+}
+
+pub fn parse_selection(input: &str, entries: &[Entry]) -> Result<Vec<String>> {
+    // comma separated 1-based numbers, ranges like 2-4, or entry names
+    let mut chosen: Vec<String> = Vec::new();
+    let index = |s: &str| -> Result<usize> {
+        match s.trim().parse::<usize>() {
+            Ok(n) if n >= 1 && n <= entries.len() => Ok(n - 1),
+            _ => Err(anyhow!(
+                "{} is not a number from 1 to {}.",
+                s,
+                entries.len()
+            )),
+        }
+    };
+    for item in parse_categories(input) {
+        let picked: Vec<usize> = if let Some(e) = entries.iter().position(|e| e.name == item) {
+            vec![e]
+        } else if let Some((from, to)) = item.split_once('-') {
+            (index(from)?..=index(to)?).collect()
+        } else {
+            vec![index(&item)?]
+        };
+        for i in picked {
+            if !chosen.contains(&entries[i].name) {
+                chosen.push(entries[i].name.clone());
+            }
+        }
+    }
+    return Ok(chosen);
+}
+
+fn substring_score(query: &str, text: &str) -> Option<u32> {
+    // 0..1000, whole matches above prefixes above anything else
+    if text == query {
+        return Some(999);
+    }
+    if text.starts_with(query) {
+        return Some(500);
+    }
+    return text.find(query).map(|_| 100);
+}
+
+pub fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    // query characters must appear in order, runs and word starts score higher
+    let text: Vec<char> = text.chars().collect();
+    let mut score: u32 = 100;
+    let mut at = 0;
+    let mut last: Option<usize> = None;
+    for q in query.chars() {
+        let i = at + text[at..].iter().position(|&c| c == q)?;
+        if last.is_some() && last == i.checked_sub(1) {
+            score += 30;
+        } else if i == 0 || matches!(text[i - 1], '/' | '.' | '_' | '-' | ' ') {
+            score += 20;
+        }
+        if let Some(l) = last {
+            score = score.saturating_sub((i - l - 1).min(10) as u32);
+        }
+        last = Some(i);
+        at = i + 1;
+    }
+    return Some(score.clamp(1, 998));
+}
+
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    // supports * for any run of characters and ? for exactly one
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            // backtrack and let the last * eat one more character
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    while pi < p.len() && p[pi] == '*' {
+        pi += 1;
+    }
+    return pi == p.len();
+}
+
+fn unsupported_store_glob(pattern: &str) -> Option<&'static str> {
+    // .troveignore only names top level store items, with * and ? as the only wildcards
+    if pattern.contains('/') {
+        return Some("only top level names in the store are matched");
+    } else if pattern.contains(['[', ']', '{', '}']) {
+        return Some("character classes and braces are not supported");
+    } else if pattern.starts_with('!') || pattern.contains('\\') {
+        return Some("negation and escapes are not supported");
+    }
+    return None;
+}
+
+pub fn is_ignored(rel: &PathBuf, patterns: &[String]) -> bool {
+    // patterns with a slash match the path inside the added directory, others any file name
+    let rel_str = rel.to_string_lossy().to_string();
+    let name = match rel.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return false,
+    };
+    for p in patterns {
+        let p = p.trim_end_matches('/');
+        let matched = if p.contains('/') {
+            glob_match(p, &rel_str)
+        } else {
+            glob_match(p, &name)
+        };
+        if matched {
+            return true;
+        }
+    }
+    return false;
+}
+
+#[cfg(unix)]
+pub fn file_mode(path: &PathBuf) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    let meta = std::fs::metadata(path).ok()?;
+    return Some(meta.permissions().mode() & 0o7777);
+}
+
+#[cfg(not(unix))]
+pub fn file_mode(_path: &PathBuf) -> Option<u32> {
+    return None;
+}
+
+#[cfg(unix)]
+pub fn apply_mode(path: &PathBuf, mode: Option<u32>) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+pub fn apply_mode(_path: &PathBuf, _mode: Option<u32>) -> Result<()> {
+    return Ok(());
+}
+
+fn collect_children(
+    dir: &PathBuf,
+    rel: &PathBuf,
+    depth: usize,
+    ignore: &[String],
+    out: &mut Vec<PathBuf>,
+) -> Result<()> {
+    // paths relative to the added directory, directories above depth are walked into
+    let mut items: Vec<std::fs::DirEntry> =
+        std::fs::read_dir(dir.join(rel))?.collect::<std::io::Result<_>>()?;
+    items.sort_by_key(|i| i.file_name());
+    for item in items {
+        let child = rel.join(item.file_name());
+        if is_ignored(&child, ignore) {
+            continue;
+        }
+        if item.file_type()?.is_dir() && depth > 1 {
+            collect_children(dir, &child, depth - 1, ignore, out)?;
+        } else {
+            out.push(child);
+        }
+    }
+    return Ok(());
+}
+
+fn has_ignored(dir: &PathBuf, rel: &PathBuf, ignore: &[String]) -> Result<bool> {
+    // whether anything under a directory matches the ignore patterns
+    if ignore.is_empty() || !dir.join(rel).is_dir() {
+        return Ok(false);
+    }
+    for item in std::fs::read_dir(dir.join(rel))? {
+        let child = rel.join(item?.file_name());
+        if is_ignored(&child, ignore) || has_ignored(dir, &child, ignore)? {
+            return Ok(true);
+        }
+    }
+    return Ok(false);
+}
+
+pub fn copy_tree(from: &PathBuf, to: &PathBuf, ignore: &[String]) -> Result<()> {
+    // recursively copies a directory, skipping anything that matches an ignore pattern
+    fn walk(root: &PathBuf, rel: &PathBuf, to: &PathBuf, ignore: &[String]) -> Result<()> {
+        std::fs::create_dir_all(to.join(rel))?;
+        for item in std::fs::read_dir(root.join(rel))? {
+            let item = item?;
+            let item_rel = rel.join(item.file_name());
+            if is_ignored(&item_rel, ignore) {
+                log::debug!("Ignoring {}", item_rel.display());
+                continue;
+            }
+            let src = root.join(&item_rel);
+            let dest = to.join(&item_rel);
+            let kind = item.file_type()?;
+            if kind.is_symlink() {
+                if std::fs::symlink_metadata(&dest).is_ok() {
+                    symlink::remove_symlink_auto(&dest)?;
+                }
+                symlink::symlink_auto(std::fs::read_link(&src)?, &dest)?;
+            } else if kind.is_dir() {
+                walk(root, &item_rel, to, ignore)?;
+            } else {
+                std::fs::copy(&src, &dest)
+                    .with_context(|| format!("Could not copy {}", src.display()))?;
+            }
+        }
+        return Ok(());
+    }
+    return walk(from, &PathBuf::new(), to, ignore);
+}
+
+pub fn content_hash(path: &PathBuf, ignore: &[String]) -> Result<String> {
+    // 64-bit FNV-1a over a file, or over every path, link target and file in a
+    // directory in name order, skipping what matches ignore as copy_tree does
+    fn feed(hash: &mut u64, bytes: &[u8]) {
+        for b in bytes {
+            *hash ^= *b as u64;
+            *hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    fn walk(hash: &mut u64, root: &PathBuf, rel: &PathBuf, ignore: &[String]) -> Result<()> {
+        // joining an empty path would add a trailing slash, which a file refuses
+        let path = if rel.as_os_str().is_empty() {
+            root.clone()
+        } else {
+            root.join(rel)
+        };
+        let kind = std::fs::symlink_metadata(&path)?.file_type();
+        feed(hash, rel.to_string_lossy().as_bytes());
+        if kind.is_symlink() {
+            feed(hash, b"\0link\0");
+            feed(
+                hash,
+                std::fs::read_link(&path)?.to_string_lossy().as_bytes(),
+            );
+        } else if kind.is_dir() {
+            feed(hash, b"\0dir\0");
+            let mut names: Vec<_> = std::fs::read_dir(&path)?
+                .map(|item| item.map(|i| i.file_name()))
+                .collect::<std::io::Result<_>>()?;
+            names.sort();
+            for name in names {
+                let item_rel = rel.join(name);
+                if !is_ignored(&item_rel, ignore) {
+                    walk(hash, root, &item_rel, ignore)?;
+                }
+            }
+        } else {
+            feed(hash, b"\0file\0");
+            feed(hash, &std::fs::read(&path)?);
+        }
+        return Ok(());
+    }
+    let mut hash: u64 = 0xcbf29ce484222325;
+    walk(&mut hash, path, &PathBuf::new(), ignore)
+        .with_context(|| format!("Could not hash {}", path.display()))?;
+    return Ok(format!("fnv1a64:{:016x}", hash));
+}
+
+pub fn disk_usage(path: &PathBuf) -> (u64, usize) {
+    // total bytes and regular file count under a path, links are not followed
+    let meta = match std::fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(_) => return (0, 0),
+    };
+    if meta.is_file() {
+        return (meta.len(), 1);
+    }
+    if !meta.is_dir() {
+        return (0, 0);
+    }
+    let mut total = (0, 0);
+    for item in std::fs::read_dir(path).into_iter().flatten().flatten() {
+        let (bytes, files) = disk_usage(&item.path());
+        total.0 += bytes;
+        total.1 += files;
+    }
+    return total;
+}
+
+pub fn link_path(target: &PathBuf, link: &PathBuf, dir_links: DirLinks) -> std::io::Result<()> {
+    // files always get a plain symlink, directories follow the configured kind
+    if !target.is_dir() {
+        return symlink::symlink_file(target, link);
+    }
+    return match dir_links {
+        DirLinks::Auto => symlink::symlink_auto(target, link),
+        DirLinks::Symlink => symlink::symlink_dir(target, link),
+        DirLinks::Junction => junction(target, link),
+    };
+}
+
+#[cfg(windows)]
+fn junction(target: &PathBuf, link: &PathBuf) -> std::io::Result<()> {
+    // mklink is a cmd builtin, junctions take an absolute target
+    let out = std::process::Command::new("cmd")
+        .args(["/C", "mklink", "/J"])
+        .arg(link)
+        .arg(std::path::absolute(target)?)
+        .output()?;
+    if !out.status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            String::from_utf8_lossy(&out.stderr).trim().to_string(),
+        ));
+    }
+    return Ok(());
+}
+
+#[cfg(not(windows))]
+fn junction(target: &PathBuf, link: &PathBuf) -> std::io::Result<()> {
+    // junctions only exist on NTFS, elsewhere a directory symlink does the same job
+    log::debug!("No junctions here, linking {} as a symlink", link.display());
+    return symlink::symlink_dir(target, link);
+}
+
+pub fn move_path(from: &PathBuf, to: &PathBuf) -> Result<()> {
+    return move_path_with(from, to, &|a, b| std::fs::rename(a, b));
+}
+
+pub fn move_path_with(
+    from: &PathBuf,
+    to: &PathBuf,
+    rename: &dyn Fn(&PathBuf, &PathBuf) -> std::io::Result<()>,
+) -> Result<()> {
+    // rename can't cross filesystems, so there the path is copied with its permissions
+    // and modification times, then removed
+    match rename(from, to) {
+        Ok(_) => return Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {}
+        Err(err) => return Err(err.into()),
+    }
+    log::debug!("{} is on another filesystem, copying it", to.display());
+    if let Err(err) = copy_preserving(from, to) {
+        // the original is untouched, so drop whatever part of the copy was made
+        let _ = remove_path(to);
+        return Err(err.context(format!("Could not copy {}", from.display())));
+    }
+    return remove_path(from);
+}
+
+fn copy_preserving(from: &PathBuf, to: &PathBuf) -> Result<()> {
+    let meta = std::fs::symlink_metadata(from)?;
+    if meta.file_type().is_symlink() {
+        symlink::symlink_auto(std::fs::read_link(from)?, to)?;
+        return Ok(());
+    }
+    if meta.is_dir() {
+        std::fs::create_dir(to)?;
+        for item in std::fs::read_dir(from)? {
+            let name = item?.file_name();
+            copy_preserving(&from.join(&name), &to.join(&name))?;
+        }
+    } else {
+        // copy keeps the permission bits already
+        std::fs::copy(from, to)?;
+    }
+    std::fs::set_permissions(to, meta.permissions())?;
+    let times = std::fs::FileTimes::new().set_modified(meta.modified()?);
+    let set = std::fs::File::options()
+        .write(!meta.is_dir())
+        .read(meta.is_dir())
+        .open(to)
+        .and_then(|f| f.set_times(times));
+    // not every platform opens directories, their times are only a nicety
+    if let (Err(err), false) = (set, meta.is_dir()) {
+        return Err(err.into());
+    }
+    return Ok(());
+}
+
+fn remove_path(path: &PathBuf) -> Result<()> {
+    let meta = std::fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else if meta.file_type().is_symlink() {
+        symlink::remove_symlink_auto(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    return Ok(());
+}
+
+pub fn store_and_link(
+    from: &PathBuf,
+    to: &PathBuf,
+    link: &dyn Fn(&PathBuf, &PathBuf) -> std::io::Result<()>,
+) -> Result<()> {
+    // moves a file into the store and links it back, moving it home again if linking fails
+    move_path(from, to)?;
+    if let Err(err) = link(to, from) {
+        if let Err(back) = move_path(to, from) {
+            return Err(anyhow!(
+                "Could not link {} ({}) or move it back ({}), it is now at {}.",
+                from.display(),
+                err,
+                back,
+                to.display()
+            ));
+        }
+        return Err(anyhow!("Could not link {}: {}", from.display(), err));
+    }
+    return Ok(());
+}
+
+pub fn clear_host_path(
+    path: &PathBuf,
+    overwrite_dir: &dyn Fn(&PathBuf) -> Result<bool>,
+) -> Result<bool> {
+    // removes whatever is at the host path so it can be relinked, directories only if allowed
+    let meta = match std::fs::symlink_metadata(path) {
+        Ok(m) => m,
+        Err(_) => return Ok(false),
+    };
+    if meta.file_type().is_symlink() {
+        symlink::remove_symlink_auto(path)?;
+    } else if meta.is_dir() {
+        if !overwrite_dir(path)? {
+            return Err(anyhow!(
+                "Refusing to overwrite directory {}.",
+                path.display()
+            ));
+        }
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    return Ok(true);
+}
+
+pub fn trove_key(home: &Home) -> Result<PathBuf> {
+    // the age identity file for encrypted entries, TROVE_KEY or ~/.trove.key
+    let key = match std::env::var_os("TROVE_KEY") {
+        Some(k) => PathBuf::from(k),
+        None => match home.dir() {
+            Some(dir) => dir.join(".trove.key"),
+            None => return Err(anyhow!("Could not find home directory.")),
+        },
+    };
+    if !key.is_file() {
+        return Err(anyhow!(
+            "No key for encrypted entries at {}, set TROVE_KEY to an age identity file.",
+            key.display()
+        ));
+    }
+    return Ok(key);
+}
+
+fn run_post_link(cmd: &str, entry: &Entry, host_path: &PathBuf) -> Result<()> {
+    // through the platform shell, with the entry and where it landed in the environment
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let out = std::process::Command::new(shell)
+        .args([flag, cmd])
+        .env("TROVE_ENTRY", &entry.name)
+        .env("TROVE_HOST_PATH", host_path)
+        .output()
+        .with_context(|| format!("Could not run post_link for {}", &entry.name))?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "post_link for {} failed ({}): {}",
+            &entry.name,
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    log::info!(
+        "Ran post_link for {}: {}",
+        &entry.name,
+        String::from_utf8_lossy(&out.stdout).trim()
+    );
+    return Ok(());
+}
+
+fn run_age(args: &[&std::ffi::OsStr]) -> Result<Vec<u8>> {
+    let out = std::process::Command::new("age")
+        .args(args)
+        .output()
+        .context("Could not run age, is it installed?")?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "age failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    return Ok(out.stdout);
+}
+
+pub fn age_encrypt(from: &PathBuf, to: &PathBuf, key: &PathBuf) -> Result<()> {
+    // encrypts to the recipient of the identity, so the same key decrypts it
+    run_age(&[
+        "-e".as_ref(),
+        "-i".as_ref(),
+        key.as_os_str(),
+        "-o".as_ref(),
+        to.as_os_str(),
+        from.as_os_str(),
+    ])?;
+    return Ok(());
+}
+
+pub fn age_decrypt(from: &PathBuf, key: &PathBuf) -> Result<Vec<u8>> {
+    return run_age(&[
+        "-d".as_ref(),
+        "-i".as_ref(),
+        key.as_os_str(),
+        from.as_os_str(),
+    ]);
+}
+
+pub fn find_conf_upward(dir: &Path) -> Option<PathBuf> {
+    // the nearest trove.conf in dir or one of its parents, the way git finds .git
+    let found = dir
+        .ancestors()
+        .map(|d| d.join("trove.conf"))
+        .find(|c| c.is_file());
+    if let Some(conf) = &found {
+        log::info!("Using {}", conf.display());
+    }
+    return found;
+}
+
+pub fn home_trove_link(home: &Home) -> Result<PathBuf> {
+    // the ~/.trove symlink that points at the active config
+    match home.dir() {
+        Some(mut home) => {
+            home.push(".trove");
+            return Ok(home);
+        }
+        None => return Err(anyhow!("Could not find home directory.")),
+    }
+}
+
+pub fn stale_link_target(link: &PathBuf) -> Option<PathBuf> {
+    // returns the target of a symlink whose target no longer exists
+    let meta = std::fs::symlink_metadata(link).ok()?;
+    if !meta.file_type().is_symlink() || link.exists() {
+        return None;
+    }
+    return std::fs::read_link(link).ok();
+}
+
+fn serialize_sorted<S: serde::Serializer>(
+    entries: &HashSet<Entry>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let mut sorted: Vec<&Entry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    return serializer.collect_seq(sorted);
+}
+
+pub fn check_entry_name(name: &str) -> Result<(), TroveError> {
+    // names that could point outside the store or be read as a flag are refused
+    let reason = if name.is_empty() {
+        Some("is empty")
+    } else if name.contains('/') || name.contains('\\') {
+        Some("contains a path separator")
+    } else if name == "." || name == ".." {
+        Some("is . or ..")
+    } else if name.starts_with('-') {
+        Some("starts with a dash")
+    } else if name.chars().any(char::is_control) {
+        Some("contains a control character")
+    } else {
+        None
+    };
+    return match reason {
+        Some(reason) => Err(TroveError::InvalidName {
+            name: name.into(),
+            reason,
+        }),
+        None => Ok(()),
+    };
+}
+
+fn check_nested_name(name: &str) -> Result<(), TroveError> {
+    // recursive adds and track nest the stored file in store subdirectories,
+    // each part of those names has to be a valid name on its own
+    for part in name.split('/') {
+        if let Err(TroveError::InvalidName { reason, .. }) = check_entry_name(part) {
+            return Err(TroveError::InvalidName {
+                name: name.into(),
+                reason,
+            });
+        }
+    }
+    return Ok(());
+}
+
+fn rename_category(categories: &[String], old: &str, new: &str) -> Vec<String> {
+    // swaps old for new in place, keeping a single copy when new was already there
+    let mut out: Vec<String> = Vec::new();
+    for c in categories {
+        let c = if c == old { new } else { c.as_str() };
+        if !out.iter().any(|k| k == c) {
+            out.push(c.to_owned());
+        }
+    }
+    return out;
+}
+
+pub fn path_category(path: &Path, n: usize, home: &Home) -> Result<String> {
+    // the nth component counting from 1 after $HOME, or after / outside of home
+    let contracted = contract_home(&normalize_path(path), home);
+    let rest = contracted.strip_prefix("$HOME").unwrap_or(&contracted);
+    let component = match n.checked_sub(1) {
+        Some(i) => Path::new(rest)
+            .components()
+            .filter_map(|c| match c {
+                std::path::Component::Normal(part) => Some(part),
+                _ => None,
+            })
+            .nth(i),
+        None => None,
+    };
+    return match component {
+        Some(c) => Ok(c.to_string_lossy().to_string()),
+        None => Err(anyhow!(
+            "{} has no path component {} to use as a category.",
+            contracted,
+            n
+        )),
+    };
+}
+
+pub fn contract_home(path: &Path, home: &Home) -> String {
+    // swaps a leading home directory for $HOME, only on whole path components;
+    // canonical paths are matched against the resolved home too, in case home is a link
+    if let Some(dir) = home.dir() {
+        let resolved = std::fs::canonicalize(&dir).ok().filter(|r| r != &dir);
+        for dir in std::iter::once(dir).chain(resolved) {
+            if let Ok(rest) = path.strip_prefix(&dir) {
+                if rest.as_os_str().is_empty() {
+                    return "$HOME".to_owned();
+                }
+                return format!("$HOME/{}", rest.to_string_lossy());
+            }
+        }
+    }
+    return path.to_string_lossy().to_string();
+}
@@ -1,440 +1,1280 @@
-use std::{collections::HashSet, io::Write, path::PathBuf};
+#![allow(
+    clippy::needless_return,
+    clippy::redundant_pattern_matching,
+    clippy::ptr_arg
+)]
+
+use std::{
+    io::{IsTerminal, Read, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
-use serde::{Deserialize, Serialize};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use dot_trove::*;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Command,
+    // repeat for more detail: -v info, -vv debug, -vvv trace
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    // only print errors and requested output, e.g. for cron
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    // treat this directory as $HOME, e.g. a container image root; TROVE_HOME also sets it
+    #[arg(long, global = true, value_name = "PATH")]
+    home: Option<PathBuf>,
+    // use a trove from the registry instead of the one ~/.trove points at
+    #[arg(long, global = true, value_name = "NAME")]
+    trove: Option<String>,
+}
+
+// set once from --quiet before any command runs
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Init {
+        path: PathBuf,
+        // re-point a dangling ~/.trove at this trove
+        #[arg(long)]
+        repair: bool,
+        // re-point ~/.trove at this trove even if it links to another one
+        #[arg(short, long, conflicts_with = "repair")]
+        force: bool,
+        // comma separated categories every new entry gets, replaces any already set
+        #[arg(long)]
+        default_categories: Option<String>,
+        // write trove.conf here instead of inside <PATH>
+        #[arg(long)]
+        config_out: Option<PathBuf>,
+        // leave ~/.trove alone and print where trove.conf is instead
+        #[arg(long, conflicts_with_all = ["repair", "force"])]
+        bare: bool,
+    },
+    // clone a repo containing a trove and init it
+    ImportGit {
+        url: String,
+        dest: PathBuf,
+        // deploy every entry once initialized
+        #[arg(long)]
+        deploy: bool,
+    },
+    Add {
+        #[arg(required_unless_present = "stdin")]
+        path: Option<PathBuf>,
+        // defaults to the file name, without a leading dot
+        name: Option<String>,
+        // repeatable, each value is one category and may contain commas
+        #[arg(short = 'c', long = "category")]
+        category: Vec<String>,
+        // comma separated, merged with any --category flags
+        #[arg(long)]
+        categories: Option<String>,
+        // comma separated globs, replaces the configured ignore list
+        #[arg(short, long)]
+        ignore: Option<String>,
+        // why this file is tracked
+        #[arg(short, long)]
+        description: Option<String>,
+        // store a symlink itself instead of the file it points to
+        #[arg(long)]
+        no_follow: bool,
+        // false tracks a file already in the store, linking it at --host
+        #[arg(long = "move", default_value_t = true, action = clap::ArgAction::Set)]
+        move_file: bool,
+        #[arg(long, required_if_eq("move_file", "false"), conflicts_with_all = ["ignore", "no_follow"])]
+        host: Option<PathBuf>,
+        // add even if another entry already deploys to the same path
+        #[arg(long)]
+        allow_conflict: bool,
+        // add each child of a directory as its own entry, named <NAME>/<child>
+        #[arg(short, long, conflicts_with_all = ["host", "no_follow"])]
+        recursive: bool,
+        // how many levels of directories to walk into with --recursive
+        #[arg(long, requires = "recursive", default_value_t = 1)]
+        depth: usize,
+        // leave out the trove's default categories for this entry
+        #[arg(long)]
+        no_default_categories: bool,
+        // also tag with the nth path component after $HOME (or /), e.g. 2 for ~/.config/<app>
+        #[arg(long, value_name = "N")]
+        category_from_path: Option<usize>,
+        // take over the name of an entry whose store file and host path are both gone
+        #[arg(long, conflicts_with_all = ["host", "recursive"])]
+        replace: bool,
+        // file name in the store, when it should differ from the entry name
+        #[arg(long, conflicts_with_all = ["host", "recursive", "encrypt"])]
+        store_name: Option<String>,
+        // store the file encrypted with age, using the key at TROVE_KEY or ~/.trove.key
+        #[arg(long, conflicts_with_all = ["ignore", "no_follow", "host", "recursive"])]
+        encrypt: bool,
+        // read paths from stdin, one per line or as path<TAB>name<TAB>categories
+        #[arg(long, conflicts_with_all = ["path", "name", "host", "recursive", "replace", "store_name", "encrypt", "category_from_path"])]
+        stdin: bool,
+    },
+    // set an entry's description, or clear it when none is given
+    Describe {
+        name: String,
+        description: Option<String>,
+    },
+    // set a command deploy runs after linking an entry, or clear it when none is given
+    PostLink {
+        name: String,
+        command: Option<String>,
+    },
+    // retag every entry in OLD with NEW instead
+    RenameCategory {
+        old: String,
+        new: String,
+    },
+    #[command(visible_alias = "rm")]
+    Remove {
+        #[arg(short, long, conflicts_with = "name")]
+        path: Option<PathBuf>,
+        #[arg(short, long)]
+        name: Option<String>,
+        // comma separated, removes every entry in any of them
+        #[arg(short, long, conflicts_with_all = ["path", "name"])]
+        category: Option<String>,
+        // required to remove every entry at once
+        #[arg(short, long)]
+        all: bool,
+        // skip the confirmation prompt, needed when not run from a terminal
+        #[arg(short, long)]
+        yes: bool,
+    },
+    #[command(visible_alias = "d")]
+    Deploy {
+        // comma separated, matches entries in any of them
+        #[arg(short, long)]
+        category: Option<String>,
+        // only match entries in every listed category
+        #[arg(long)]
+        all_categories: bool,
+        #[arg(short, long, conflicts_with = "category")]
+        name: Option<String>,
+        // replace whatever already exists at the host path
+        #[arg(short, long)]
+        force: bool,
+        // leave correct links alone, only create missing ones and fix wrong links
+        #[arg(long)]
+        only_missing: bool,
+        // fail entries missing from the store instead of skipping them
+        #[arg(long)]
+        strict: bool,
+        // deploy under this directory as if it were $HOME, to try a trove out
+        #[arg(long)]
+        target_dir: Option<PathBuf>,
+        // repeatable, leave these entries out
+        #[arg(long)]
+        exclude: Vec<String>,
+        // repeatable, leave out entries in these categories
+        #[arg(long, visible_alias = "not-category")]
+        exclude_category: Vec<String>,
+        // first remove dangling links into the store left where no entry deploys anymore
+        #[arg(long)]
+        clean: bool,
+        // list the selected entries by category and prompt for which to deploy
+        #[arg(short, long, conflicts_with = "name")]
+        interactive: bool,
+        // also write each entry's outcome as JSON to this file
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+        // deploy entries concurrently, one job per CPU unless --jobs says otherwise
+        #[arg(long)]
+        parallel: bool,
+        // how many entries to deploy at once, implies --parallel
+        #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: Option<u32>,
+        // only deploy entries whose stored files changed between this git ref and HEAD
+        #[arg(long, value_name = "REF")]
+        since: Option<String>,
+    },
+    Pack {
+        // comma separated, matches entries in any of them
+        #[arg(short, long)]
+        category: Option<String>,
+        // only match entries in every listed category
+        #[arg(long)]
+        all_categories: bool,
+        #[arg(short, long, conflicts_with = "category")]
+        name: Option<String>,
+        // required to pack every entry at once
+        #[arg(short, long)]
+        all: bool,
+        // pack links made by deploy --target-dir
+        #[arg(long)]
+        target_dir: Option<PathBuf>,
+        // repeatable, leave these entries out
+        #[arg(long)]
+        exclude: Vec<String>,
+        // repeatable, leave out entries in these categories
+        #[arg(long, visible_alias = "not-category")]
+        exclude_category: Vec<String>,
+        // also write each entry's outcome as JSON to this file
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+        // move stored files back to their host paths, keeping the entries for the next deploy
+        #[arg(long, conflicts_with = "target_dir")]
+        restore: bool,
+    },
+    // pack then deploy, clearing links at old host paths in between
+    Redeploy {
+        // comma separated, matches entries in any of them
+        #[arg(short, long)]
+        category: Option<String>,
+        // only match entries in every listed category
+        #[arg(long)]
+        all_categories: bool,
+        #[arg(short, long, conflicts_with = "category")]
+        name: Option<String>,
+    },
+    // check that deployed links point into the store
+    Verify {
+        #[arg(long)]
+        json: bool,
+        // resolve symlinks on both sides before checking links point into the store
+        #[arg(long, default_value_t = false, action = clap::ArgAction::Set)]
+        follow_symlinks: bool,
+    },
+    // compare copied and encrypted entries' host content with the hash recorded at add
+    CheckDrift {
+        // record the current host content as the expected hash instead
+        #[arg(long)]
+        update: bool,
+    },
+    // verify, and with --fix repair what can safely be repaired
+    Doctor {
+        // trove to re-point a stale ~/.trove at, as with init --repair
+        path: Option<PathBuf>,
+        #[arg(long)]
+        fix: bool,
+        // remove dangling links without asking
+        #[arg(short, long, requires = "fix")]
+        yes: bool,
+    },
+    // list store files no entry refers to
+    Prune {
+        // remove them instead of just listing
+        #[arg(long)]
+        delete: bool,
+    },
+    // print an entry's store path then its host path, one per line
+    Which {
+        name: String,
+        #[arg(long, conflicts_with = "host_only")]
+        store_only: bool,
+        #[arg(long)]
+        host_only: bool,
+    },
+    // relocate the store and relink deployed entries into it
+    MoveStore {
+        new_path: PathBuf,
+    },
+    // find entries by name, category or host path
+    Search {
+        query: String,
+        // match the query's characters in order rather than as one substring
+        #[arg(long)]
+        fuzzy: bool,
+        #[arg(long)]
+        json: bool,
+    },
+    // ls, as entries are listed here with --long
+    #[command(visible_alias = "ls")]
+    Status {
+        // comma separated, only look at entries in any of them
+        #[arg(short, long)]
+        category: Option<String>,
+        // only look at this entry
+        #[arg(short, long, conflicts_with = "category")]
+        name: Option<String>,
+        // list entries with store paths relative to the store root
+        #[arg(long)]
+        store_relative: bool,
+        // only list problem entries, exiting 1 if there are any
+        #[arg(long, conflicts_with_all = ["store_relative", "long"])]
+        broken: bool,
+        // list entries with when they were added
+        #[arg(short, long)]
+        long: bool,
+        // order of listed entries
+        #[arg(long, value_enum, default_value_t = SortBy::Name)]
+        sort: SortBy,
+        // dump the loaded trove as is, the old default output
+        #[arg(long, conflicts_with_all = ["store_relative", "broken", "long"])]
+        debug: bool,
+        // print the summary, listing or problems as json
+        #[arg(long, conflicts_with = "debug")]
+        json: bool,
+    },
+    // entry counts, store size and the largest entries
+    Stats {
+        // how many of the largest entries to list
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+        #[arg(long)]
+        json: bool,
+    },
+    Config {
+        #[arg(long)]
+        json: bool,
+        #[command(subcommand)]
+        action: Option<ConfigAction>,
+    },
+    // name a trove's config in the registry so --trove can select it
+    Register {
+        name: String,
+        config: PathBuf,
+        // replace a trove already registered under the name
+        #[arg(short, long)]
+        force: bool,
+    },
+    // list registered troves, marking the one ~/.trove points at
+    Troves,
+    // print exports for TROVE_STORE and TROVE_CONFIG, for eval "$(dot-trove env)"
+    Env {
+        #[arg(long)]
+        fish: bool,
+    },
+    Undo,
+    // show journaled adds and removes, most recent first
+    Log {
+        // only show this many records
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    #[command(hide = true)]
+    Completions {
+        shell: Shell,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
-struct Entry {
-    name: String,
-    host_path: String,
-    categories: Vec<String>,
+impl Command {
+    fn json(&self) -> bool {
+        // errors are printed as json too when the command's output is
+        return matches!(
+            self,
+            Command::Verify { json: true, .. }
+                | Command::Search { json: true, .. }
+                | Command::Status { json: true, .. }
+                | Command::Stats { json: true, .. }
+                | Command::Config { json: true, .. }
+        );
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct TroveConfig {
-    path: String,
-    store_path: String,
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    // change a setting in trove.conf, lists are comma separated
+    Set { key: String, value: String },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Trove {
-    config: TroveConfig,
-    entries: HashSet<Entry>,
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum SortBy {
+    Name,
+    // oldest first, entries without a time last
+    Added,
 }
 
-impl Trove {
-    pub fn find_entry_by_name(&self, name: &str) -> Option<Entry> {
-        for e in &self.entries {
-            if e.name == name {
-                return Some(e.clone());
-            }
+fn main() {
+    let cli = Cli::parse();
+    let json = cli.command.json();
+    if let Err(err) = run(cli) {
+        // the trove error under any added context picks the exit code
+        let code = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<TroveError>())
+            .map_or(1, TroveError::exit_code);
+        if json {
+            eprintln!(
+                "{}",
+                serde_json::json!({ "error": format!("{:#}", err), "code": code })
+            );
+        } else {
+            eprintln!("Error: {:?}", err);
         }
-        return None;
+        std::process::exit(code);
     }
+}
 
-    pub fn find_entry_by_path(&self, path: &PathBuf) -> Option<Entry> {
-        for e in &self.entries {
-            let mut p = self.config.store_path.clone();
-            p.push_str("/");
-            p.push_str(&e.name.clone());
+fn run(cli: Cli) -> Result<()> {
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    let level = match cli.verbose {
+        _ if cli.quiet => log::LevelFilter::Error,
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        2 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_timestamp(None)
+        .format_target(false)
+        .init();
 
-            if &get_true_path(&p) == path {
-                return Some(e.clone());
-            }
-        }
-        return None;
+    if let Command::Completions { shell } = &cli.command {
+        // print a completion script for the given shell, no trove needed
+        let mut cmd = Cli::command();
+        let bin = cmd.get_name().to_string();
+        clap_complete::generate(*shell, &mut cmd, bin, &mut std::io::stdout());
+        return Ok(());
     }
-
-    pub fn find_entry_by_category(&self, category: &String) -> Option<HashSet<Entry>> {
-        // might be better to do this functionally
-        let mut out = HashSet::new();
-        for e in &self.entries {
-            if e.categories.contains(category) {
-                out.insert(e.to_owned());
-            }
-        }
-        if out.len() == 0 {
-            return None;
-        } else {
-            return Some(out);
+    // resolves $HOME and ~/.trove
+    let home = Home::with_override(cli.home.clone())?;
+    if let Command::Register {
+        name,
+        config,
+        force,
+    } = &cli.command
+    {
+        let mut registry = Registry::load(&home)?;
+        registry.register(name, config, &home, *force)?;
+        registry.save(&home)?;
+        say(format!("Registered {}", name));
+        return Ok(());
+    }
+    if let Command::Troves = &cli.command {
+        let registry = Registry::load(&home)?;
+        let default = registry.default_name(&home);
+        for (name, conf) in &registry.troves {
+            let mark = if default.as_ref() == Some(name) {
+                " (default)"
+            } else {
+                ""
+            };
+            println!("{}\t{}{}", name, expand_home(conf, &home).display(), mark);
         }
+        return Ok(());
     }
-
-    pub fn load(p: Option<PathBuf>) -> Result<Self> {
-        let mut conf = PathBuf::new();
-        match p {
-            Some(path) => conf = path,
-            None => {
-                if let Some(mut path) = dirs_next::home_dir() {
-                    path.push(".trove");
-                    conf = path;
+    // a --trove name picks the config, otherwise ~/.trove does
+    let selected = match &cli.trove {
+        Some(name) => Some(Registry::load(&home)?.resolve(name, &home)?),
+        None => None,
+    };
+    if let Command::Init {
+        path,
+        repair,
+        force,
+        default_categories,
+        config_out,
+        bare,
+    } = &cli.command
+    {
+        // have to test for this, as all other commands require a trove set up
+        // check  if the directory exists
+        let abs = get_absolute_path(path)?;
+        let conf = match config_out {
+            Some(c) => std::env::current_dir()?.join(c),
+            None => abs.join("trove.conf"),
+        };
+        if let Ok(targ) = get_absolute_path(&conf) {
+            // trove exists, just create symlink
+            let mut trove = Trove::load(Some(targ.clone()), home)?;
+            set_default_categories(&mut trove, default_categories)?;
+            if *repair {
+                if let Some(old) = trove.repair_conf_symlink(&targ)? {
+                    say(format!("Removed stale link to {}", old.display()));
+                }
+            } else {
+                // the trove may sit outside an overridden home, so re-resolve its paths
+                trove.relocate(&targ)?;
+                if *bare {
+                    println!("{}", targ.display());
+                } else {
+                    link_conf(&trove, *force)?;
                 }
             }
+        } else if *repair {
+            return Err(anyhow!("No trove.conf found at {}.", conf.display()));
+        } else {
+            // make a new trove
+            let mut trove = Trove::create_with_config(abs, conf.clone(), home)?;
+            set_default_categories(&mut trove, default_categories)?;
+            if *bare {
+                println!("{}", conf.display());
+            } else {
+                link_conf(&trove, *force)?;
+            }
         }
-        if let Ok(path) = get_absolute_path(&conf) {
-            let json = json_from_file(&path)?;
-            let trove: Trove = serde_json::from_value(json)?;
-            return Ok(trove);
+        return Ok(());
+    }
+    if let Command::ImportGit { url, dest, deploy } = &cli.command {
+        let mut trove = Trove::import_git(url, dest, home)?;
+        link_conf(&trove, false)?;
+        if *deploy {
+            let opts = DeployOptions::default();
+            let report = trove.deploy_command(&None, &None, CategoryMatch::Any, &opts)?;
+            return print_report(&report, "deploy");
         }
-        return Err(anyhow!(
-            "Could not find a valid .trove file. \r\n Run `trove init <path>` to begin."
-        ));
+        return Ok(());
     }
-
-    pub fn create(path: PathBuf) -> Result<Self> {
-        // create the trove.conf file
-        let mut conf = path.clone();
-        conf.push("trove.conf");
-        let mut store = path.clone();
-        store.push("store");
-        let trove = Trove {
-            config: TroveConfig {
-                path: get_relative_path(&conf),
-                store_path: get_relative_path(&store.clone()),
-            },
-            entries: HashSet::new(),
-        };
-
-        let cont = serde_json::to_string_pretty(&trove)?;
-        json_to_file(&get_true_path(&trove.config.path), &cont)?;
-
-        if let Err(_) = std::fs::DirBuilder::new().create(store) {}
-
-        trove.create_conf_symlink()?;
-
-        return Ok(trove);
+    if let Command::Env { fish } = &cli.command {
+        let config = Trove::load_config(selected.clone(), &home)?;
+        let vars = [
+            ("TROVE_STORE", expand_home(&config.store_path, &home)),
+            ("TROVE_CONFIG", expand_home(&config.path, &home)),
+        ];
+        for (var, path) in vars {
+            let value = path.to_string_lossy();
+            if *fish {
+                println!("set -gx {} {};", var, fish_quote(&value));
+            } else {
+                println!("export {}={}", var, shell_quote(&value));
+            }
+        }
+        return Ok(());
     }
-
-    pub fn save(&self) -> Result<()> {
-        let cont = serde_json::to_string_pretty(self)?;
-        json_to_file(&get_true_path(&self.config.path), &cont)?;
-
+    if let Command::Config {
+        action: Some(ConfigAction::Set { key, value }),
+        ..
+    } = &cli.command
+    {
+        let mut trove = Trove::load(selected.clone(), home)?;
+        trove.config.set(key, value)?;
+        trove.save()?;
         return Ok(());
     }
-
-    fn create_conf_symlink(&self) -> Result<()> {
-        // create symlink to home dir
-        if let Some(mut home) = dirs_next::home_dir() {
-            home.push(PathBuf::from(".trove"));
-            match symlink::symlink_file(get_true_path(&self.config.path), home) {
-                Ok(_) => Ok(()),
-                Err(_) => {
-                    println!(
-                        "Already initialized to: {}",
-                        get_true_path(&self.config.path).display()
-                    );
-                    Ok(())
-                }
+    if let Command::Config { json, .. } = &cli.command {
+        let config = Trove::load_config(selected.clone(), &home)?;
+        let conf_path = expand_home(&config.path, &home);
+        let store_path = expand_home(&config.store_path, &home);
+        if *json {
+            let mut out = serde_json::json!({
+                "config": conf_path,
+                "store": store_path,
+            });
+            // settings keep their json types here
+            let typed = serde_json::to_value(&config)?;
+            for key in CONFIG_KEYS {
+                out[key] = typed[key].clone();
             }
+            println!("{}", serde_json::to_string_pretty(&out)?);
         } else {
-            return Err(anyhow!("Could not find home directory."));
+            println!("config: {}", conf_path.display());
+            println!("store: {}", store_path.display());
+            for (key, value) in config.settings() {
+                println!("{}: {}", key, value);
+            }
         }
+        return Ok(());
     }
-
-    fn add_entry(&mut self, path: PathBuf, name: &str, categories: Option<String>) -> Result<()> {
-        let cats: Vec<String> = match categories {
-            Some(s) => {
-                // split on commas
-                let split: Vec<String> = s
-                    .split(",")
-                    .filter(|x| !x.is_empty())
-                    .map(|s| s.to_owned())
-                    .collect::<Vec<String>>();
-                split
-            }
-            None => Vec::new(),
+    if let Command::Doctor { path, fix, yes } = &cli.command {
+        let (mut trove, conf) = match path {
+            Some(p) => {
+                let conf = get_absolute_path(&p.join("trove.conf"))
+                    .map_err(|_| anyhow!("No trove.conf found at {}.", p.display()))?;
+                (Trove::load(Some(conf.clone()), home)?, conf)
+            }
+            None => {
+                let trove = Trove::load(selected.clone(), home)?;
+                let conf = expand_home(&trove.config.path, &trove.home);
+                (trove, conf)
+            }
         };
-        // check if the name/path is already loaded
-        if let Some(_) = self.find_entry_by_name(name) {
-            return Err(anyhow!("Entry by that name already exists."));
-        }
-        if let Some(_) = self.find_entry_by_path(&path) {
-            return Err(anyhow!("Entry with that path already exists."));
+        if !*fix {
+            let report = trove.verify_command(false)?;
+            for (name, reason) in &report.failed {
+                println!("{}: {}", name, reason);
+            }
+            return report.check("verify");
         }
-        let host_path = get_absolute_path(&path)?;
-        let mut host_path_str = host_path.to_string_lossy().to_string();
-        if let Some(home) = dirs_next::home_dir() {
-            let clean = home.to_string_lossy().to_string();
-            if host_path_str.contains(&clean) {
-                host_path_str = PathBuf::from(host_path_str.replace(&clean, "$HOME"))
-                    .to_string_lossy()
-                    .to_string();
+        let interactive = std::io::stdin().is_terminal();
+        let report = trove.doctor_command(&conf, &|e, link| {
+            if *yes {
+                return Ok(true);
+            }
+            if !interactive {
+                return Ok(false);
             }
+            let prompt = format!(
+                "{} is missing from the store, remove the link at {}? [y/N] ",
+                &e.name,
+                link.display()
+            );
+            return confirm(&prompt);
+        })?;
+        for note in &report.notes {
+            say(note);
         }
-
-        let entry = Entry {
-            name: name.into(),
-            host_path: host_path_str,
-            categories: cats,
-        };
-
-        self.entries.insert(entry);
-        self.save()?;
-
-        return Ok(());
-    }
-
-    fn remove_entry(&mut self, entry: &Entry) -> Result<()> {
-        self.entries.remove(entry);
-
-        self.save()?;
-        Ok(())
-    }
-
-    fn add_command(
-        &mut self,
-        path: &PathBuf,
-        name: &String,
-        categories: &Option<String>,
-    ) -> Result<()> {
-        let from_path = get_absolute_path(&path)?;
-        let mut to_path = get_true_path(&self.config.store_path);
-        to_path.push(name);
-        self.add_entry(from_path.clone(), name, categories.clone())?;
-        std::fs::rename(&from_path, &to_path)?;
-
-        symlink::symlink_auto(&to_path, &from_path)?;
-
-        return Ok(());
+        for (name, reason) in &report.failed {
+            println!("{}: {}", name, reason);
+        }
+        say(format!(
+            "Found {} issues, fixed {}.",
+            report.done.len() + report.failed.len(),
+            report.done.len()
+        ));
+        return report.check("fix");
     }
-
-    fn deploy_command(&self, category: &Option<String>, name: &Option<String>) -> Result<()> {
-        match (category, name) {
-            (None, None) => {
-                let mut from_path = PathBuf::new();
-                for e in &self.entries {
-                    from_path.clear();
-                    from_path = get_true_path(&self.config.store_path);
-                    from_path.push(&e.name);
-                    if let Err(_) = symlink::symlink_auto(&from_path, get_true_path(&e.host_path)) {
-                        println!("Could not deploy {}", &e.name);
-                    }
+    // get trove
+    let mut trove = Trove::load(selected, home)?;
+    // run normal command workflows
+    match &cli.command {
+        Command::Remove {
+            path,
+            name,
+            category,
+            all,
+            yes,
+        } => {
+            match trove.removal_targets(path, name, category)? {
+                Some(entries) => confirm_remove(&entries, &trove.home, *yes)?,
+                None if !trove.entries.is_empty() => {
+                    // --yes only answers the per-entry prompt, everything takes --all
+                    confirm_all("Remove", trove.entries.len(), *all)?
                 }
+                None => {}
             }
-            (None, Some(n)) => {
-                if let Some(entry) = self.find_entry_by_name(n) {
-                    let mut from_path = get_true_path(&self.config.store_path);
-                    from_path.push(&entry.name);
-                    if let Err(_) =
-                        symlink::symlink_auto(&from_path, get_true_path(&entry.host_path))
-                    {
-                        println!("Could not deploy {}", &entry.name);
-                    }
-                } else {
-                    return Err(anyhow!("No entry found by that name."));
-                }
+            let report = trove.remove_command(path, name, category)?;
+            if category.is_some() {
+                print_summary(&report, name, "Removed");
             }
-            (Some(c), None) => {
-                let mut from_path = PathBuf::new();
-                if let Some(entries) = self.find_entry_by_category(c) {
-                    for e in entries {
-                        from_path.clear();
-                        from_path = get_true_path(&self.config.store_path);
-                        from_path.push(&e.name);
-                        if let Err(_) =
-                            symlink::symlink_auto(&from_path, get_true_path(&e.host_path))
-                        {
-                            println!("Could not deploy {}", &e.name);
+            return print_report(&report, "remove");
+        }
+        Command::Deploy {
+            category,
+            all_categories,
+            name,
+            force,
+            only_missing,
+            strict,
+            target_dir,
+            exclude,
+            exclude_category,
+            clean,
+            interactive,
+            report: report_file,
+            parallel,
+            jobs,
+            since,
+        } => {
+            let overwrite_dir = |path: &PathBuf| {
+                let prompt = format!("{} is a directory, delete it? [y/N] ", path.display());
+                return confirm(&prompt);
+            };
+            let mut opts = DeployOptions {
+                force: *force,
+                only_missing: *only_missing,
+                strict: *strict,
+                overwrite_dir: Some(&overwrite_dir),
+                target_dir: absolute_target_dir(target_dir)?,
+                exclude: Exclude {
+                    names: exclude.clone(),
+                    categories: exclude_category.clone(),
+                },
+                jobs: match (jobs, parallel) {
+                    (Some(n), _) => *n as usize,
+                    (None, true) => std::thread::available_parallelism().map_or(1, |n| n.get()),
+                    (None, false) => 1,
+                },
+            };
+            let mode = CategoryMatch::from_flag(*all_categories);
+            if let Some(git_ref) = since {
+                // anything unchanged is excluded, without git everything deploys
+                match trove.changed_since(git_ref)? {
+                    Some(changed) if changed.is_empty() => {
+                        say(format!("Nothing changed since {}.", git_ref));
+                        return Ok(());
+                    }
+                    Some(changed) => {
+                        for e in &trove.entries {
+                            if !changed.contains(&e.name) {
+                                opts.exclude.names.push(e.name.clone());
+                            }
                         }
                     }
-                } else {
-                    return Err(anyhow!("No entries found."));
+                    None => log::warn!("Deploying every selected entry instead"),
                 }
             }
-            (Some(_), Some(_)) => return Err(anyhow!("Please specify only one criteria.")),
-        }
-
-        return Ok(());
-    }
-
-    fn pack_command(&self, category: &Option<String>, name: &Option<String>) -> Result<()> {
-        match (category, name) {
-            (None, None) => {
-                for e in &self.entries {
-                    if let Err(_) = symlink::remove_symlink_auto(get_true_path(&e.host_path)) {}
+            if *interactive {
+                // whatever isn't picked is excluded from the deploy
+                let offered = trove.select_entries(category, &None, mode, &opts.exclude)?;
+                let chosen = pick_entries(&offered)?;
+                if chosen.is_empty() {
+                    say("Nothing selected.");
+                    return Ok(());
                 }
-            }
-            (None, Some(n)) => {
-                if let Some(entry) = self.find_entry_by_name(n) {
-                    if let Err(_) = symlink::remove_symlink_auto(get_true_path(&entry.host_path)) {}
-                } else {
-                    return Err(anyhow!("No entry found by that name."));
+                for e in offered.iter().filter(|e| !chosen.contains(&e.name)) {
+                    opts.exclude.names.push(e.name.clone());
                 }
             }
-            (Some(c), None) => {
-                if let Some(entries) = self.find_entry_by_category(c) {
-                    for e in entries {
-                        if let Err(_) = symlink::remove_symlink_auto(get_true_path(&e.host_path)) {}
-                    }
-                } else {
-                    return Err(anyhow!("No entries found."));
+            if *clean {
+                print_report(&trove.clean_links(&opts.target_dir)?, "clean")?;
+            }
+            let report = trove.deploy_command(category, name, mode, &opts)?;
+            if let Some(file) = report_file {
+                RunReport::new("deploy", &report)?.write(file)?;
+            }
+            print_summary(&report, name, "Deployed");
+            return print_report(&report, "deploy");
+        }
+        Command::Pack {
+            category,
+            all_categories,
+            name,
+            all,
+            target_dir,
+            exclude,
+            exclude_category,
+            report: report_file,
+            restore,
+        } => {
+            let opts = PackOptions {
+                target_dir: absolute_target_dir(target_dir)?,
+                exclude: Exclude {
+                    names: exclude.clone(),
+                    categories: exclude_category.clone(),
+                },
+            };
+            if category.is_none() && name.is_none() {
+                confirm_all("Pack", trove.entries.len(), *all)?;
+            }
+            let mode = CategoryMatch::from_flag(*all_categories);
+            let report = if *restore {
+                trove.pack_restore_command(category, name, mode, &opts.exclude)?
+            } else {
+                trove.pack_command(category, name, mode, &opts)?
+            };
+            if let Some(file) = report_file {
+                RunReport::new("pack", &report)?.write(file)?;
+            }
+            print_summary(&report, name, "Packed");
+            return print_report(&report, "pack");
+        }
+        Command::Redeploy {
+            category,
+            all_categories,
+            name,
+        } => {
+            // relinking is safe, so there is no need to confirm packing everything
+            let report = trove.redeploy_command(
+                category,
+                name,
+                CategoryMatch::from_flag(*all_categories),
+            )?;
+            print_summary(&report, name, "Redeployed");
+            return print_report(&report, "deploy");
+        }
+        Command::MoveStore { new_path } => {
+            let report = trove.move_store_command(new_path)?;
+            return print_report(&report, "relink");
+        }
+        Command::Which {
+            name,
+            store_only,
+            host_only,
+        } => {
+            let entry = match trove.find_entry_by_name(name) {
+                Some(e) => e,
+                None => return Err(TroveError::NotFound(name.clone()).into()),
+            };
+            if !*host_only {
+                println!("{}", trove.stored_path(&entry).display());
+            }
+            if !*store_only {
+                println!("{}", expand_home(&entry.host_path, &trove.home).display());
+            }
+            return Ok(());
+        }
+        Command::Prune { delete } => {
+            let report = trove.prune_command(*delete)?;
+            if report.done.is_empty() && report.skipped.is_empty() && report.failed.is_empty() {
+                say("Nothing to prune.");
+            }
+            return print_report(&report, "prune");
+        }
+        Command::Verify {
+            json,
+            follow_symlinks,
+        } => {
+            let report = trove.verify_command(*follow_symlinks)?;
+            if *json {
+                print_json(&VerifyResult::from(&report))?;
+            } else {
+                for (name, reason) in &report.failed {
+                    println!("{}: {}", name, reason);
                 }
             }
-            (Some(_), Some(_)) => return Err(anyhow!("Please specify only one criteria.")),
+            return report.check("verify");
         }
-        return Ok(());
-    }
-
-    fn remove_command(&mut self, path: &Option<PathBuf>, name: &Option<String>) -> Result<()> {
-        match (path, name) {
-            (None, None) => return Err(anyhow!("Need criteria to remove by.")),
-            (None, Some(n)) => {
-                if let Some(e) = &self.find_entry_by_name(n) {
-                    self.remove_entry(e)?;
-                    if let Err(_) = symlink::remove_symlink_auto(get_true_path(&e.host_path)) {
-                        println!("Symlink does not exists, continuing...",);
+        Command::CheckDrift { update } => {
+            let report = trove.check_drift_command(*update)?;
+            for (name, reason) in report.skipped.iter().chain(&report.failed) {
+                println!("{}: {}", name, reason);
+            }
+            if *update {
+                say(report.summary("Hashed"));
+            }
+            return report.check("check drift of");
+        }
+        Command::Log { limit } => {
+            let records = trove.log_command(*limit)?;
+            if records.is_empty() {
+                say("No history.");
+            }
+            for r in &records {
+                let op = match r.operation {
+                    Operation::Add => "add",
+                    Operation::Remove => "remove",
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    rfc3339(r.timestamp),
+                    op,
+                    &r.entry.name,
+                    &r.store_path,
+                    &r.host_path
+                );
+            }
+            return Ok(());
+        }
+        Command::Undo => {
+            let record = trove.undo_command()?;
+            match record.operation {
+                Operation::Add => say(format!("Undid add of {}", &record.entry.name)),
+                Operation::Remove => say(format!("Undid remove of {}", &record.entry.name)),
+            }
+            return Ok(());
+        }
+        Command::Status {
+            category,
+            name,
+            store_relative,
+            broken,
+            long,
+            sort,
+            debug,
+            json,
+        } => {
+            if category.is_some() || name.is_some() {
+                // every mode below then works on the narrowed set
+                let none = Exclude::default();
+                let selected = trove.select_entries(category, name, CategoryMatch::Any, &none)?;
+                trove.entries.retain(|e| selected.contains(e));
+            }
+            if *broken {
+                let found = trove.broken_entries();
+                if *json {
+                    let problems: Vec<Problem> = found
+                        .iter()
+                        .map(|(e, reason)| Problem {
+                            name: e.name.clone(),
+                            reason: reason.to_string(),
+                        })
+                        .collect();
+                    print_json(&problems)?;
+                } else {
+                    for (e, reason) in &found {
+                        println!("{}\t{}", &e.name, reason);
                     }
-                    let mut from_path = get_true_path(&self.config.store_path);
-                    from_path.push(&e.name);
-                    std::fs::rename(from_path, get_true_path(&e.host_path))?;
-                    return Ok(());
                 }
+                if !found.is_empty() {
+                    std::process::exit(1);
+                }
+                return Ok(());
             }
-            (Some(p), None) => {
-                let abs = get_absolute_path(&p)?;
-                if let Some(e) = &self.find_entry_by_path(&abs) {
-                    self.remove_entry(e)?;
-                    if let Err(_) = symlink::remove_symlink_auto(get_true_path(&e.host_path)) {
-                        println!("Symlink does not exists, continuing...",);
-                    }
-                    let mut from_path = get_true_path(&self.config.store_path);
-                    from_path.push(&e.name);
-                    std::fs::rename(from_path, get_true_path(&e.host_path))?;
-                    return Ok(());
+            if *debug {
+                println!("{:?}", &trove);
+                return Ok(());
+            }
+            if !*store_relative && !*long {
+                if *json {
+                    return print_json(&trove.status_summary());
                 }
+                print_status(&trove);
+                return Ok(());
+            }
+            let mut entries: Vec<Entry> = trove.entries_sorted().into_iter().cloned().collect();
+            match sort {
+                SortBy::Name => {}
+                SortBy::Added => entries.sort_by(|a, b| {
+                    let key = |e: &Entry| (e.added_at.is_none(), e.added_at.clone());
+                    key(a).cmp(&key(b)).then_with(|| a.name.cmp(&b.name))
+                }),
+            }
+            if *json {
+                return print_listing(&trove, &entries, *store_relative);
             }
-            (Some(_), Some(_)) => return Err(anyhow!("Please specify only one criteria.")),
+            print_entries(&trove, &entries, *store_relative, *long);
+            return Ok(());
         }
-        return Err(anyhow!("Entry doesn't exists."));
-    }
-}
-
-#[derive(Subcommand, Debug)]
-enum Command {
-    Init {
-        path: PathBuf,
-    },
-    Add {
-        path: PathBuf,
-        name: String,
-        #[arg(short, long)]
-        categories: Option<String>,
-    },
-    Remove {
-        #[arg(short, long)]
-        path: Option<PathBuf>,
-        #[arg(short, long)]
-        name: Option<String>,
-    },
-    Deploy {
-        #[arg(short, long)]
-        category: Option<String>,
-        #[arg(short, long)]
-        name: Option<String>,
-    },
-    Pack {
-        #[arg(short, long)]
-        category: Option<String>,
-        #[arg(short, long)]
-        name: Option<String>,
-    },
-    Status,
-}
-
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-
-    if let Command::Init { path } = &cli.command {
-        // have to test for this, as all other commands require a trove set up
-        // check  if the directory exists
-        let abs = get_absolute_path(&path)?;
-        let mut conf = abs.clone();
-        conf.push("trove.conf");
-        if let Ok(targ) = get_absolute_path(&conf) {
-            // trove exists, just create symlink
-            let trove = Trove::load(Some(targ))?;
-            trove.create_conf_symlink()?;
-        } else {
-            // make a new trove
-            let _trove = Trove::create(abs)?;
+        Command::Stats { top, json } => {
+            let stats = trove.stats_command(*top);
+            if *json {
+                return print_json(&stats);
+            }
+            println!("entries: {} ({} deployed)", stats.entries, stats.deployed);
+            println!(
+                "store: {} in {} files",
+                human_size(stats.store_bytes),
+                stats.files
+            );
+            let categories: Vec<String> = stats
+                .categories
+                .iter()
+                .map(|c| format!("{} ({})", c.name, c.entries))
+                .collect();
+            if !categories.is_empty() {
+                println!("categories: {}", categories.join(", "));
+            }
+            if !stats.largest.is_empty() {
+                println!("largest:");
+            }
+            for e in &stats.largest {
+                println!("  {:>10}  {}", human_size(e.bytes), &e.name);
+            }
+            return Ok(());
         }
-        return Ok(());
-    }
-    // get trove
-    let mut trove = Trove::load(None)?;
-    // run normal command workflows
-    match &cli.command {
-        Command::Remove { path, name } => trove.remove_command(path, name),
-        Command::Deploy { category, name } => trove.deploy_command(category, name),
-        Command::Pack { category, name } => trove.pack_command(category, name),
-        Command::Status => {
-            println!("{:?}", &trove);
+        Command::Search { query, fuzzy, json } => {
+            let found = trove.search_command(query, *fuzzy);
+            if *json {
+                return print_listing(&trove, &found, false);
+            }
+            if found.is_empty() {
+                say("No entries matched.");
+            }
+            print_entries(&trove, &found, false, false);
             return Ok(());
         }
         Command::Add {
             path,
             name,
+            category,
             categories,
-        } => trove.add_command(path, name, categories),
+            ignore,
+            description,
+            no_follow,
+            move_file,
+            host,
+            allow_conflict,
+            recursive,
+            depth,
+            no_default_categories,
+            category_from_path,
+            replace,
+            store_name,
+            encrypt,
+            stdin,
+        } => {
+            let mut categories = merge_categories(category, categories);
+            if *stdin {
+                if !*no_default_categories {
+                    categories = trove.with_default_categories(&categories);
+                }
+                let mut list = String::new();
+                std::io::stdin().read_to_string(&mut list)?;
+                let opts = AddOptions {
+                    categories,
+                    ignore: ignore.clone(),
+                    description: description.clone(),
+                    no_follow: *no_follow,
+                    allow_conflict: *allow_conflict,
+                    ..AddOptions::default()
+                };
+                let report = trove.add_list_command(&list, &opts);
+                say(report.summary("Added"));
+                return print_report(&report, "add");
+            }
+            let path = path
+                .as_ref()
+                .ok_or_else(|| anyhow!("A path is required without --stdin."))?;
+            let path = &expand_tilde(path, &trove.home);
+            let host = &host.as_ref().map(|h| expand_tilde(h, &trove.home));
+            if let Some(n) = category_from_path {
+                // taken from the path as given, so a linked ~/.config still counts from $HOME
+                let source = std::env::current_dir()?.join(host.as_ref().unwrap_or(path));
+                let c = path_category(&source, *n, &trove.home)?;
+                if !categories.contains(&c) {
+                    categories.push(c);
+                }
+            }
+            if !*no_default_categories {
+                categories = trove.with_default_categories(&categories);
+            }
+            let opts = AddOptions {
+                name: name.clone(),
+                store_name: store_name.clone(),
+                categories,
+                ignore: ignore.clone(),
+                description: description.clone(),
+                no_follow: *no_follow,
+                allow_conflict: *allow_conflict,
+            };
+            if *recursive {
+                let report = trove.add_recursive_command(path, *depth, &opts)?;
+                print_summary(&report, &None, "Added");
+                return print_report(&report, "add");
+            }
+            if *replace {
+                let entry_name = match name {
+                    Some(n) => n.clone(),
+                    None if *no_follow => derive_name(&get_absolute_path_no_follow(path)?)?,
+                    None => derive_name(&get_absolute_path(path)?)?,
+                };
+                if let Some(old) = trove.replace_stale_entry(&entry_name)? {
+                    say(format!("Replaced stale entry {}", &old.name));
+                }
+            }
+            match (move_file, host) {
+                (false, Some(host)) => {
+                    trove.track_command(path, host, &opts)?;
+                }
+                (true, Some(_)) => return Err(anyhow!("--host only applies with --move=false.")),
+                _ if *encrypt => {
+                    trove.encrypt_command(path, &opts)?;
+                }
+                _ => {
+                    trove.add_command(path, &opts)?;
+                }
+            }
+            return Ok(());
+        }
+        Command::Describe { name, description } => {
+            trove.describe_command(name, description.clone())?;
+            return Ok(());
+        }
+        Command::PostLink { name, command } => {
+            trove.post_link_command(name, command.clone())?;
+            return Ok(());
+        }
+        Command::RenameCategory { old, new } => {
+            let renamed = trove.rename_category_command(old, new)?;
+            say(format!(
+                "Renamed {} to {} on {} entries.",
+                old,
+                new,
+                renamed.len()
+            ));
+            return Ok(());
+        }
         _ => unreachable!("Invalid Command"),
     }
 }
 
-//util functions
-pub fn json_from_file(path: &PathBuf) -> Result<serde_json::Value> {
-    let file = std::fs::File::open(path)?;
+fn set_default_categories(trove: &mut Trove, categories: &Option<String>) -> Result<()> {
+    if let Some(c) = categories {
+        trove.config.default_categories = parse_categories(c);
+        trove.save()?;
+    }
+    return Ok(());
+}
+
+fn absolute_target_dir(dir: &Option<PathBuf>) -> Result<Option<PathBuf>> {
+    return match dir {
+        Some(dir) => Ok(Some(std::env::current_dir()?.join(dir))),
+        None => Ok(None),
+    };
+}
 
-    let json = serde_json::from_reader(file).expect("JSON was misformatted.");
+fn say(msg: impl std::fmt::Display) {
+    // informational output, silenced by --quiet
+    if !QUIET.load(Ordering::Relaxed) {
+        println!("{}", msg);
+    }
+}
 
-    return Ok(json);
+fn print_status(trove: &Trove) {
+    // a short overview, entries themselves are listed with --long
+    let summary = trove.status_summary();
+    println!("config: {}", summary.config.display());
+    println!("store: {}", summary.store.display());
+    let mut line = format!(
+        "entries: {} ({} deployed, {} not deployed",
+        summary.entries,
+        summary.deployed,
+        summary.entries - summary.deployed
+    );
+    if summary.broken > 0 {
+        line.push_str(&format!(", {} broken", summary.broken));
+    }
+    println!("{})", line);
+    let categories: Vec<String> = summary
+        .categories
+        .iter()
+        .map(|c| format!("{} ({})", c.name, c.entries))
+        .collect();
+    if !categories.is_empty() {
+        println!("categories: {}", categories.join(", "));
+    }
 }
 
-pub fn json_to_file(path: &PathBuf, contents: &str) -> Result<()> {
-    let mut file = std::fs::File::create(path)?;
-    file.write_all(contents.as_bytes())?;
+fn human_size(bytes: u64) -> String {
+    // binary units, one decimal once past bytes
+    let units = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    return format!("{:.1} {}", size, units[unit]);
+}
+
+fn print_json(value: &impl serde::Serialize) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
     return Ok(());
 }
 
-pub fn get_absolute_path(rel: &PathBuf) -> Result<PathBuf> {
-    // converts from relative path to absolute
-    let mut path = std::env::current_dir()?;
-    path.push(rel);
-    // this also Err if path doesn't exist
-    match std::fs::canonicalize(path) {
-        Ok(r) => return Ok(r),
-        Err(_) => return Err(anyhow!("Path does not exist or isn't a directory.")),
+fn print_listing(trove: &Trove, entries: &[Entry], store_relative: bool) -> Result<()> {
+    let listing: Vec<EntryListing> = entries
+        .iter()
+        .map(|e| trove.entry_listing(e, store_relative))
+        .collect();
+    return print_json(&listing);
+}
+
+fn print_entries(trove: &Trove, entries: &[Entry], store_relative: bool, long: bool) {
+    // one tab separated line per entry: name, stored path, host path, and with long
+    // when and where it was added and why
+    for e in entries {
+        let mut line = format!(
+            "{}\t{}\t{}",
+            &e.name,
+            trove.display_stored_path(e, store_relative).display(),
+            &e.host_path
+        );
+        if long {
+            line.push('\t');
+            line.push_str(e.added_at.as_deref().unwrap_or("-"));
+            line.push('\t');
+            line.push_str(e.origin.as_deref().unwrap_or("-"));
+            line.push('\t');
+            line.push_str(e.description.as_deref().unwrap_or("-"));
+        }
+        println!("{}", line);
+    }
+}
+
+fn print_summary(report: &Report, name: &Option<String>, verb: &str) {
+    // a single entry gets a short confirmation, anything else a tally
+    if let Some(n) = name {
+        match report.skipped.first() {
+            Some((_, reason)) => say(format!("Skipped {} ({})", n, reason)),
+            None => say(format!("{} {}", verb, n)),
+        }
+        return;
+    }
+    say(report.summary(verb));
+}
+
+fn print_report(report: &Report, action: &str) -> Result<()> {
+    for note in &report.notes {
+        say(note);
+    }
+    return report.check(action);
+}
+
+fn link_conf(trove: &Trove, force: bool) -> Result<()> {
+    let conf = expand_home(&trove.config.path, &trove.home);
+    if force {
+        match trove.replace_conf_symlink()? {
+            Some(old) if old != conf => say(format!(
+                "Switched from {} to {}",
+                old.display(),
+                conf.display()
+            )),
+            _ => say(format!("Initialized to: {}", conf.display())),
+        }
+        return Ok(());
+    }
+    if !trove.create_conf_symlink()? {
+        say(format!("Already initialized to: {}", conf.display()));
     }
+    return Ok(());
+}
+
+fn confirm(prompt: &str) -> Result<bool> {
+    // asks a y/N question on stdin, anything but yes is a no
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    return Ok(answer == "y" || answer == "yes");
 }
 
-pub fn get_true_path(path: &String) -> PathBuf {
-    // converts absolute paths with $HOME shorthands to full paths
-    if let Some(home) = dirs_next::home_dir() {
-        let clean = home.to_string_lossy().to_string();
-        if path.contains("$HOME") {
-            return PathBuf::from(path.replace("$HOME", &clean));
+fn pick_entries(entries: &[Entry]) -> Result<Vec<String>> {
+    // numbered by category, then read back as numbers, ranges or names
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!("--interactive needs a terminal to prompt on."));
+    }
+    let mut numbered = Vec::new();
+    for (category, group) in group_by_category(entries) {
+        println!("{}:", category.as_deref().unwrap_or("uncategorized"));
+        for e in group {
+            println!("  {:>3}) {}\t{}", numbered.len() + 1, &e.name, &e.host_path);
+            numbered.push(e);
         }
     }
-    return PathBuf::from(path);
+    print!("Deploy which entries? (e.g. 1,3-5,vimrc) ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    return parse_selection(&answer, &numbered);
+}
+
+fn confirm_all(action: &str, count: usize, all: bool) -> Result<()> {
+    // bare bulk commands need --all or an explicit yes before touching every entry
+    if all {
+        return Ok(());
+    }
+    // nobody is there to answer, so a piped or redirected stdin is a no
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "Refusing to {} every entry without --all when not run interactively.",
+            action.to_lowercase()
+        ));
+    }
+    let prompt = format!("{} all {} entries? [y/N] ", action, count);
+    if confirm(&prompt)? {
+        return Ok(());
+    }
+    return Err(anyhow!(
+        "Refusing to {} every entry without --all.",
+        action.to_lowercase()
+    ));
 }
 
-fn get_relative_path(path: &PathBuf) -> String {
-    // converts full paths to relative paths with $HOME shorthands
-    let mut path_str = path.to_string_lossy().to_string();
-    if let Some(home) = dirs_next::home_dir() {
-        let clean = home.to_string_lossy().to_string();
-        if path_str.contains(&clean) {
-            path_str = PathBuf::from(path_str.replace(&clean, "$HOME"))
-                .to_string_lossy()
-                .to_string();
+fn confirm_remove(entries: &[Entry], home: &Home, yes: bool) -> Result<()> {
+    // restoring to a wrong host path is hard to undo, so ask first
+    if yes {
+        return Ok(());
+    }
+    let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "Refusing to remove {} without --yes when not run interactively.",
+            names.join(", ")
+        ));
+    }
+    let prompt = match entries {
+        [e] => format!(
+            "Remove entry '{}' and restore to {}? [y/N] ",
+            &e.name,
+            expand_home(&e.host_path, home).display()
+        ),
+        _ => {
+            for e in entries {
+                println!("{}\t{}", &e.name, expand_home(&e.host_path, home).display());
+            }
+            format!(
+                "Remove these {} entries and restore them? [y/N] ",
+                entries.len()
+            )
         }
+    };
+    if confirm(&prompt)? {
+        return Ok(());
     }
-    return path_str;
+    return Err(anyhow!("Not removing {}.", names.join(", ")));
 }
@@ -0,0 +1,286 @@
+#![allow(clippy::needless_return)]
+
+use std::path::{Path, PathBuf};
+
+use dot_trove::*;
+
+fn bob() -> Home {
+    return Home::at(PathBuf::from("/home/bob"));
+}
+
+#[test]
+fn contract_swaps_leading_home() {
+    let home = bob();
+    assert_eq!(
+        contract_home(Path::new("/home/bob/.vimrc"), &home),
+        "$HOME/.vimrc"
+    );
+    assert_eq!(
+        contract_home(Path::new("/home/bob/.config/nvim"), &home),
+        "$HOME/.config/nvim"
+    );
+    assert_eq!(contract_home(Path::new("/home/bob"), &home), "$HOME");
+}
+
+#[test]
+fn contract_ignores_home_mid_path() {
+    let home = bob();
+    assert_eq!(
+        contract_home(Path::new("/tmp/home/bob-backup"), &home),
+        "/tmp/home/bob-backup"
+    );
+    assert_eq!(
+        contract_home(Path::new("/tmp/home/bob/.vimrc"), &home),
+        "/tmp/home/bob/.vimrc"
+    );
+}
+
+#[test]
+fn contract_matches_whole_components_only() {
+    let home = bob();
+    assert_eq!(
+        contract_home(Path::new("/home/bob-backup/.vimrc"), &home),
+        "/home/bob-backup/.vimrc"
+    );
+    assert_eq!(
+        contract_home(Path::new("/home/bobby"), &home),
+        "/home/bobby"
+    );
+}
+
+#[test]
+fn contract_replaces_only_the_first_occurrence() {
+    let home = bob();
+    assert_eq!(
+        contract_home(Path::new("/home/bob/mirror/home/bob/.vimrc"), &home),
+        "$HOME/mirror/home/bob/.vimrc"
+    );
+}
+
+#[test]
+fn expand_only_touches_leading_shorthand() {
+    let home = bob();
+    assert_eq!(
+        expand_home("$HOME/.vimrc", &home),
+        PathBuf::from("/home/bob/.vimrc")
+    );
+    assert_eq!(expand_home("$HOME", &home), PathBuf::from("/home/bob"));
+    assert_eq!(
+        expand_home("/tmp/$HOME-ish", &home),
+        PathBuf::from("/tmp/$HOME-ish")
+    );
+    assert_eq!(
+        expand_home("$HOMEWORK/notes", &home),
+        PathBuf::from("$HOMEWORK/notes")
+    );
+}
+
+#[test]
+fn contract_then_expand_round_trips() {
+    let home = bob();
+    for p in [
+        "/home/bob/.ssh/config",
+        "/etc/hosts",
+        "/tmp/home/bob-backup",
+    ] {
+        let path = PathBuf::from(p);
+        assert_eq!(expand_home(&contract_home(&path, &home), &home), path);
+    }
+}
+
+#[test]
+fn expand_resolves_set_variables() {
+    let home = bob();
+    std::env::set_var("DOT_TROVE_TEST_XDG", "/home/bob/.config");
+    assert_eq!(
+        expand_home("$DOT_TROVE_TEST_XDG/nvim", &home),
+        PathBuf::from("/home/bob/.config/nvim")
+    );
+    assert!(unset_vars("$DOT_TROVE_TEST_XDG/nvim").is_empty());
+}
+
+#[test]
+fn expand_leaves_unset_variables() {
+    let home = bob();
+    std::env::remove_var("DOT_TROVE_TEST_UNSET");
+    assert_eq!(
+        expand_home("$DOT_TROVE_TEST_UNSET/nvim", &home),
+        PathBuf::from("$DOT_TROVE_TEST_UNSET/nvim")
+    );
+    assert_eq!(
+        unset_vars("$DOT_TROVE_TEST_UNSET/nvim"),
+        vec!["DOT_TROVE_TEST_UNSET".to_string()]
+    );
+}
+
+#[test]
+fn expand_handles_multiple_variables() {
+    let home = bob();
+    std::env::set_var("DOT_TROVE_TEST_APP", "nvim");
+    std::env::set_var("DOT_TROVE_TEST_FILE", "init.lua");
+    std::env::remove_var("DOT_TROVE_TEST_GONE");
+    assert_eq!(
+        expand_home(
+            "$HOME/.config/$DOT_TROVE_TEST_APP/$DOT_TROVE_TEST_FILE",
+            &home
+        ),
+        PathBuf::from("/home/bob/.config/nvim/init.lua")
+    );
+    assert_eq!(
+        expand_home("/opt/$DOT_TROVE_TEST_APP/$DOT_TROVE_TEST_GONE", &home),
+        PathBuf::from("/opt/nvim/$DOT_TROVE_TEST_GONE")
+    );
+}
+
+#[test]
+fn contract_handles_home_itself_and_its_children() {
+    let home = bob();
+    assert_eq!(contract_home(Path::new("/home/bob/"), &home), "$HOME");
+    assert_eq!(
+        contract_home(Path::new("/home/bob/.vimrc"), &home),
+        "$HOME/.vimrc"
+    );
+    assert_eq!(expand_home("$HOME/", &home), PathBuf::from("/home/bob"));
+
+    // a home dir given with a trailing slash contracts the same way
+    let slashed = Home::at(PathBuf::from("/home/bob/"));
+    assert_eq!(contract_home(Path::new("/home/bob"), &slashed), "$HOME");
+    assert_eq!(
+        contract_home(Path::new("/home/bob/.vimrc"), &slashed),
+        "$HOME/.vimrc"
+    );
+}
+
+#[test]
+fn contract_then_expand_round_trips_at_home() {
+    for home in [bob(), Home::at(PathBuf::from("/home/bob/"))] {
+        for p in [
+            "/home/bob",
+            "/home/bob/",
+            "/home/bob/.vimrc",
+            "/home/bob/.config/",
+        ] {
+            let path = PathBuf::from(p);
+            let round = expand_home(&contract_home(&path, &home), &home);
+            assert_eq!(round, path, "{}", p);
+            assert_eq!(round.components().count(), path.components().count());
+        }
+    }
+}
+
+#[test]
+fn expand_keeps_literal_home_text_in_file_names() {
+    let home = bob();
+    assert_eq!(
+        expand_home("$HOME/x/$HOME-themed", &home),
+        PathBuf::from("/home/bob/x/$HOME-themed")
+    );
+    assert_eq!(
+        expand_home("$HOME/$HOME/.vimrc", &home),
+        PathBuf::from("/home/bob/$HOME/.vimrc")
+    );
+    assert_eq!(
+        expand_home("/srv/themes/$HOME", &home),
+        PathBuf::from("/srv/themes/$HOME")
+    );
+    assert!(unset_vars("$HOME/x/$HOME-themed").is_empty());
+
+    let path = PathBuf::from("/home/bob/x/$HOME-themed");
+    assert_eq!(contract_home(&path, &home), "$HOME/x/$HOME-themed");
+    assert_eq!(expand_home(&contract_home(&path, &home), &home), path);
+}
+
+#[test]
+fn home_override_prefers_the_flag_then_trove_home() {
+    std::env::set_var("TROVE_HOME", "/srv/image/root");
+    let home = Home::with_override(None).unwrap();
+    assert_eq!(home.dir(), Some(PathBuf::from("/srv/image/root")));
+    assert_eq!(
+        expand_home("$HOME/.vimrc", &home),
+        PathBuf::from("/srv/image/root/.vimrc")
+    );
+
+    let home = Home::with_override(Some(PathBuf::from("/home/bob"))).unwrap();
+    assert_eq!(home.dir(), Some(PathBuf::from("/home/bob")));
+
+    // relative overrides are taken from the working directory
+    let home = Home::with_override(Some(PathBuf::from("rootfs"))).unwrap();
+    assert_eq!(
+        home.dir(),
+        Some(std::env::current_dir().unwrap().join("rootfs"))
+    );
+    std::env::remove_var("TROVE_HOME");
+}
+
+#[test]
+fn normalize_drops_trailing_slashes_and_dots() {
+    for (raw, clean) in [
+        ("/home/bob/somedir/", "/home/bob/somedir"),
+        ("/home/bob/./somedir//", "/home/bob/somedir"),
+        ("/home/bob/x/../somedir", "/home/bob/somedir"),
+        ("/..", "/"),
+        ("somedir/../../x", "../x"),
+    ] {
+        assert_eq!(
+            normalize_path(Path::new(raw)),
+            PathBuf::from(clean),
+            "{}",
+            raw
+        );
+    }
+}
+
+#[test]
+fn path_category_counts_from_home_or_root() {
+    let home = bob();
+    let init = Path::new("/home/bob/.config/nvim/lua/plugins.lua");
+    assert_eq!(path_category(init, 1, &home).unwrap(), ".config");
+    assert_eq!(path_category(init, 2, &home).unwrap(), "nvim");
+    assert_eq!(
+        path_category(Path::new("/home/bob/./.config//kitty/"), 2, &home).unwrap(),
+        "kitty"
+    );
+    assert_eq!(
+        path_category(Path::new("/etc/nginx/nginx.conf"), 2, &home).unwrap(),
+        "nginx"
+    );
+
+    let err = path_category(Path::new("/home/bob/.vimrc"), 2, &home).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "$HOME/.vimrc has no path component 2 to use as a category."
+    );
+    assert!(path_category(init, 0, &home).is_err());
+}
+
+#[test]
+fn expand_tilde_resolves_a_leading_tilde() {
+    let home = bob();
+    assert_eq!(
+        expand_tilde(Path::new("~/.vimrc"), &home),
+        PathBuf::from("/home/bob/.vimrc")
+    );
+    assert_eq!(
+        expand_tilde(Path::new("~"), &home),
+        PathBuf::from("/home/bob")
+    );
+    assert_eq!(
+        expand_tilde(Path::new("~/"), &home),
+        PathBuf::from("/home/bob")
+    );
+    for p in ["~bob/.vimrc", "notes/~/x", "/tmp/~", "~.vimrc"] {
+        assert_eq!(expand_tilde(Path::new(p), &home), PathBuf::from(p), "{}", p);
+    }
+}
+
+#[test]
+fn parse_categories_trims_dedups_and_drops_empty_parts() {
+    assert_eq!(parse_categories("a, b,,a"), ["a", "b"]);
+    assert_eq!(
+        parse_categories("  shell ,editor, shell,  "),
+        ["shell", "editor"]
+    );
+    assert_eq!(parse_categories("b,a"), ["b", "a"]);
+    assert!(parse_categories("").is_empty());
+    assert!(parse_categories(" , ,").is_empty());
+}
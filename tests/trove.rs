@@ -0,0 +1,3094 @@
+#![allow(clippy::needless_return, clippy::ptr_arg)]
+
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use dot_trove::*;
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// a throwaway home directory, removed when dropped
+struct TempHome {
+    dir: PathBuf,
+}
+
+impl TempHome {
+    fn new() -> Self {
+        let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("dot-trove-test-{}-{}", std::process::id(), n));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = std::fs::canonicalize(dir).unwrap();
+        return TempHome { dir };
+    }
+
+    fn home(&self) -> Home {
+        return Home::at(self.dir.clone());
+    }
+
+    fn path(&self, rel: &str) -> PathBuf {
+        return self.dir.join(rel);
+    }
+
+    fn write(&self, rel: &str, contents: &str) -> PathBuf {
+        let path = self.path(rel);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, contents).unwrap();
+        return path;
+    }
+
+    fn init(&self) -> Trove {
+        std::fs::create_dir_all(self.path("trove")).unwrap();
+        let trove = Trove::create(self.path("trove"), self.home()).unwrap();
+        assert!(trove.create_conf_symlink().unwrap());
+        return trove;
+    }
+
+    fn conf(&self) -> serde_json::Value {
+        let cont = std::fs::read_to_string(self.path("trove/trove.conf")).unwrap();
+        return serde_json::from_str(&cont).unwrap();
+    }
+}
+
+impl Drop for TempHome {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn is_symlink(path: &PathBuf) -> bool {
+    return std::fs::symlink_metadata(path)
+        .map(|m| m.file_type().is_symlink())
+        .unwrap_or(false);
+}
+
+fn trove_error(err: anyhow::Error) -> TroveError {
+    return err.downcast::<TroveError>().unwrap();
+}
+
+#[test]
+fn init_writes_config_relative_to_home() {
+    let tmp = TempHome::new();
+    tmp.init();
+
+    let conf = tmp.conf();
+    assert_eq!(conf["config"]["path"], "$HOME/trove/trove.conf");
+    assert_eq!(conf["config"]["store_path"], "$HOME/trove/store");
+    assert!(tmp.path("trove/store").is_dir());
+    assert_eq!(
+        std::fs::read_link(tmp.path(".trove")).unwrap(),
+        tmp.path("trove/trove.conf")
+    );
+
+    // loading through ~/.trove finds the same trove
+    let trove = Trove::load(None, tmp.home()).unwrap();
+    assert_eq!(trove.config.path, "$HOME/trove/trove.conf");
+}
+
+#[test]
+fn add_moves_file_into_store_and_links_it() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+
+    let entry = trove
+        .add_command(
+            &vimrc,
+            &AddOptions {
+                categories: vec!["editor".into()],
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(entry.name, "vimrc");
+
+    assert!(is_symlink(&vimrc));
+    assert_eq!(
+        std::fs::read_to_string(tmp.path("trove/store/vimrc")).unwrap(),
+        "set nu"
+    );
+    let conf = tmp.conf();
+    assert_eq!(conf["entries"][0]["name"], "vimrc");
+    assert_eq!(conf["entries"][0]["host_path"], "$HOME/.vimrc");
+    assert_eq!(conf["entries"][0]["categories"][0], "editor");
+}
+
+#[test]
+fn add_rejects_names_that_are_not_file_names() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let somefile = tmp.write("somefile", "x");
+
+    let err = trove
+        .add_command(
+            &somefile,
+            &AddOptions {
+                name: Some("../escape".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap_err();
+    assert!(matches!(trove_error(err), TroveError::InvalidName { .. }));
+    assert!(!is_symlink(&somefile));
+    assert!(!tmp.path("trove/escape").exists());
+    assert!(trove.entries.is_empty());
+
+    for name in [
+        "..",
+        ".",
+        "/etc/passwd",
+        "a//b",
+        "nvim/init.lua",
+        "nvim\\init.lua",
+        "nvim/../..",
+        "-rf",
+        "tab\there",
+        "nul\0byte",
+        "",
+    ] {
+        assert!(check_entry_name(name).is_err(), "{:?}", name);
+    }
+    assert!(check_entry_name("nvim.lua").is_ok());
+    assert!(check_entry_name("..vimrc").is_ok());
+}
+
+#[test]
+fn add_rejects_path_separators_in_names() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let somefile = tmp.write("somefile", "x");
+
+    for opts in [
+        AddOptions {
+            name: Some("nvim/init.lua".into()),
+            ..AddOptions::default()
+        },
+        AddOptions {
+            name: Some("nvim\\init.lua".into()),
+            ..AddOptions::default()
+        },
+        AddOptions {
+            store_name: Some("nvim/init.lua".into()),
+            ..AddOptions::default()
+        },
+    ] {
+        let err = trove.add_command(&somefile, &opts).unwrap_err();
+        assert!(matches!(
+            trove_error(err),
+            TroveError::InvalidName { reason, .. } if reason == "contains a path separator"
+        ));
+    }
+    assert!(!is_symlink(&somefile));
+    assert!(!tmp.path("trove/store/nvim").exists());
+    assert!(trove.entries.is_empty());
+}
+
+#[test]
+fn add_recursive_checks_each_part_of_a_nested_name() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    tmp.write(".config/nvim/init.lua", "");
+    let dashed = tmp.write(".config/-rf", "");
+    let config = tmp.path(".config");
+
+    let opts = AddOptions {
+        name: Some("..".into()),
+        ..AddOptions::default()
+    };
+    let err = trove.add_recursive_command(&config, 1, &opts).unwrap_err();
+    assert!(matches!(trove_error(err), TroveError::InvalidName { .. }));
+    assert!(trove.entries.is_empty());
+
+    let report = trove
+        .add_recursive_command(&config, 1, &AddOptions::default())
+        .unwrap();
+    assert_eq!(report.done, ["config/nvim"]);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "config/-rf");
+    assert!(report.failed[0].1.contains("starts with a dash"));
+    assert!(!is_symlink(&dashed));
+    assert!(!tmp.path("trove/store/config/-rf").exists());
+}
+
+#[test]
+fn track_rejects_dot_dot_in_a_nested_name() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let stored = tmp.write("trove/store/a/b", "");
+    let host = tmp.path(".b");
+
+    let opts = AddOptions {
+        name: Some("a/../b".into()),
+        ..AddOptions::default()
+    };
+    let err = trove.track_command(&stored, &host, &opts).unwrap_err();
+    assert!(matches!(
+        trove_error(err),
+        TroveError::InvalidName { name, .. } if name == "a/../b"
+    ));
+    let err = trove.add_entry(host.clone(), &opts).unwrap_err();
+    assert!(matches!(trove_error(err), TroveError::InvalidName { .. }));
+    assert!(!is_symlink(&host));
+    assert!(trove.entries.is_empty());
+}
+
+#[test]
+fn add_and_remove_expand_a_literal_tilde() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+
+    let entry = trove
+        .add_command(&PathBuf::from("~/.vimrc"), &AddOptions::default())
+        .unwrap();
+    assert_eq!(entry.host_path, "$HOME/.vimrc");
+    assert!(is_symlink(&vimrc));
+
+    let found = trove
+        .removal_targets(&Some(PathBuf::from("~/.vimrc")), &None, &None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(found[0].name, "vimrc");
+
+    // the stdin list goes through the same intake
+    tmp.write(".zshrc", "export A=1");
+    let report = trove.add_list_command("~/.zshrc\n", &AddOptions::default());
+    assert_eq!(report.done, vec!["zshrc".to_string()]);
+    assert!(is_symlink(&tmp.path(".zshrc")));
+}
+
+#[test]
+fn bulk_output_and_trove_conf_list_entries_by_name() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let names = [
+        "alacritty",
+        "bashrc",
+        "gitconfig",
+        "tmux.conf",
+        "vimrc",
+        "zshrc",
+    ];
+    for name in names.iter().rev() {
+        let path = tmp.write(&format!(".{}", name), "");
+        trove.add_command(&path, &AddOptions::default()).unwrap();
+    }
+
+    let sorted: Vec<&str> = trove
+        .entries_sorted()
+        .iter()
+        .map(|e| e.name.as_str())
+        .collect();
+    assert_eq!(sorted, names);
+    let conf = tmp.conf();
+    let written: Vec<&str> = conf["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(written, names);
+    assert_eq!(trove.verify_command(false).unwrap().done, names);
+    let report = trove
+        .pack_command(&None, &None, CategoryMatch::Any, &PackOptions::default())
+        .unwrap();
+    assert_eq!(report.done, names);
+    let report = trove.remove_command(&None, &None, &None).unwrap();
+    assert_eq!(report.done, names);
+}
+
+#[test]
+fn add_rejects_paths_already_in_the_store() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let stray = tmp.write("trove/store/stray.conf", "x = 1");
+    let before = tmp.conf();
+
+    let err = trove
+        .add_command(&stray, &AddOptions::default())
+        .unwrap_err();
+    assert!(matches!(trove_error(err), TroveError::InStore { path, .. } if path == stray));
+    assert!(trove.entries.is_empty());
+    assert_eq!(tmp.conf(), before);
+    assert!(!is_symlink(&stray));
+    assert_eq!(std::fs::read_to_string(&stray).unwrap(), "x = 1");
+}
+
+#[test]
+fn find_entry_by_path_follows_a_deployed_link() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    assert!(is_symlink(&vimrc));
+
+    assert_eq!(trove.find_entry_by_path(&vimrc).unwrap().name, "vimrc");
+    // the file the link resolves to is the same entry
+    let stored = tmp.path("trove/store/vimrc");
+    assert_eq!(trove.find_entry_by_path(&stored).unwrap().name, "vimrc");
+    assert!(trove.find_entry_by_path(&tmp.path(".zshrc")).is_none());
+}
+
+#[test]
+fn find_entry_by_path_matches_the_host_path_while_packed() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".config/vim/vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    trove
+        .pack_command(&None, &None, CategoryMatch::Any, &PackOptions::default())
+        .unwrap();
+    assert!(std::fs::symlink_metadata(&vimrc).is_err());
+
+    assert_eq!(trove.find_entry_by_path(&vimrc).unwrap().name, "vimrc");
+    let dotted = tmp.path(".config/./vim/../vim/vimrc");
+    assert_eq!(trove.find_entry_by_path(&dotted).unwrap().name, "vimrc");
+}
+
+#[test]
+fn pack_and_deploy_toggle_the_link() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    trove.add_command(&zshrc, &AddOptions::default()).unwrap();
+    let any = CategoryMatch::Any;
+
+    let report = trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    assert_eq!(report.done, vec!["zshrc".to_string()]);
+    assert!(!zshrc.exists());
+    assert!(!is_symlink(&zshrc));
+
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert_eq!(report.done, vec!["zshrc".to_string()]);
+    assert!(is_symlink(&zshrc));
+    assert_eq!(std::fs::read_to_string(&zshrc).unwrap(), "export A=1");
+}
+
+#[test]
+fn pack_restore_externalizes_until_the_next_deploy() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    trove.add_command(&zshrc, &AddOptions::default()).unwrap();
+    let any = CategoryMatch::Any;
+
+    let report = trove
+        .pack_restore_command(&None, &None, any, &Exclude::default())
+        .unwrap();
+    assert_eq!(report.done, vec!["zshrc".to_string()]);
+    assert!(!is_symlink(&zshrc));
+    assert!(!tmp.path("trove/store/zshrc").exists());
+    assert_eq!(tmp.conf()["entries"][0]["externalized"], true);
+    assert!(trove.verify_command(false).unwrap().failed.is_empty());
+    let report = trove
+        .pack_restore_command(&None, &None, any, &Exclude::default())
+        .unwrap();
+    assert_eq!(report.skipped[0].1, "already externalized");
+
+    // edits made while externalized are what goes back into the store
+    std::fs::write(&zshrc, "export A=2").unwrap();
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert_eq!(report.done, vec!["zshrc".to_string()]);
+    assert!(is_symlink(&zshrc));
+    assert_eq!(
+        std::fs::read_to_string(tmp.path("trove/store/zshrc")).unwrap(),
+        "export A=2"
+    );
+    assert!(tmp.conf()["entries"][0].get("externalized").is_none());
+}
+
+#[test]
+fn parallel_deploy_reports_like_a_serial_one() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let mut names = Vec::new();
+    for i in 0..12 {
+        let path = tmp.write(&format!(".config/app{}/rc", i), "x");
+        let name = format!("app{:02}", i);
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    name: Some(name.clone()),
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+        names.push(name);
+    }
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    // one entry can't deploy, the rest still should
+    std::fs::write(tmp.path(".config/app3/rc"), "in the way").unwrap();
+
+    let opts = DeployOptions {
+        jobs: 4,
+        ..DeployOptions::default()
+    };
+    let report = trove.deploy_command(&None, &None, any, &opts).unwrap();
+    names.retain(|n| n != "app03");
+    assert_eq!(report.done, names);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].0, "app03");
+    assert!(is_symlink(&tmp.path(".config/app11/rc")));
+}
+
+#[test]
+fn deploy_creates_missing_parent_directories() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let conf = tmp.write(".config/app/app.toml", "x = 1");
+    trove.add_command(&conf, &AddOptions::default()).unwrap();
+    let any = CategoryMatch::Any;
+
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    std::fs::remove_dir_all(tmp.path(".config")).unwrap();
+    trove
+        .deploy_command(
+            &None,
+            &Some("app.toml".into()),
+            any,
+            &DeployOptions::default(),
+        )
+        .unwrap();
+    assert!(is_symlink(&conf));
+}
+
+#[test]
+fn remove_restores_file_and_forgets_entry() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let bashrc = tmp.write(".bashrc", "alias l=ls");
+    trove.add_command(&bashrc, &AddOptions::default()).unwrap();
+
+    let report = trove
+        .remove_command(&None, &Some("bashrc".into()), &None)
+        .unwrap();
+    assert_eq!(report.done, vec!["bashrc".to_string()]);
+    assert!(!is_symlink(&bashrc));
+    assert_eq!(std::fs::read_to_string(&bashrc).unwrap(), "alias l=ls");
+    assert!(!tmp.path("trove/store/bashrc").exists());
+    assert_eq!(tmp.conf()["entries"].as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn move_store_relinks_deployed_entries() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    trove.add_command(&zshrc, &AddOptions::default()).unwrap();
+    trove
+        .pack_command(
+            &None,
+            &Some("zshrc".into()),
+            CategoryMatch::Any,
+            &PackOptions::default(),
+        )
+        .unwrap();
+
+    let report = trove.move_store_command(&tmp.path("disk/store")).unwrap();
+    assert_eq!(report.done, vec!["vimrc".to_string()]);
+    assert!(!tmp.path("trove/store").exists());
+    assert_eq!(tmp.conf()["config"]["store_path"], "$HOME/disk/store");
+    assert_eq!(
+        std::fs::read_link(&vimrc).unwrap(),
+        tmp.path("disk/store/vimrc")
+    );
+    assert_eq!(std::fs::read_to_string(&vimrc).unwrap(), "set nu");
+    // packed entries stay packed
+    assert!(!is_symlink(&zshrc));
+}
+
+#[test]
+fn move_store_refuses_its_own_subdirectory_however_it_is_spelled() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    symlink::symlink_dir(tmp.path("trove/store"), tmp.path("alias")).unwrap();
+
+    for new in ["trove/store/../store/sub", "trove/./store/sub", "alias/sub"] {
+        let err = trove.move_store_command(&tmp.path(new)).unwrap_err();
+        assert!(err.to_string().contains("inside itself"), "{}", new);
+    }
+    assert!(tmp.path("trove/store/vimrc").is_file());
+    assert_eq!(tmp.conf()["config"]["store_path"], "$HOME/trove/store");
+}
+
+#[test]
+fn move_store_refuses_existing_target() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    std::fs::create_dir_all(tmp.path("elsewhere")).unwrap();
+
+    assert!(trove.move_store_command(&tmp.path("elsewhere")).is_err());
+    assert!(tmp.path("trove/store").is_dir());
+    assert_eq!(tmp.conf()["config"]["store_path"], "$HOME/trove/store");
+}
+
+#[test]
+fn load_upgrades_unversioned_config() {
+    let tmp = TempHome::new();
+    std::fs::create_dir_all(tmp.path("trove/store")).unwrap();
+    let conf = tmp.write(
+        "trove/trove.conf",
+        r#"{
+  "config": {
+    "path": "$HOME/trove/trove.conf",
+    "store_path": "$HOME/trove/store"
+  },
+  "entries": [
+    {
+      "name": "vimrc",
+      "host_path": "$HOME/.vimrc",
+      "categories": ["editor"]
+    }
+  ]
+}"#,
+    );
+
+    let trove = Trove::load(Some(conf), tmp.home()).unwrap();
+    assert_eq!(trove.config.version, CONFIG_VERSION);
+    assert!(trove.config.ignore.is_empty());
+    let entry = trove.find_entry_by_name("vimrc").unwrap();
+    assert_eq!(entry.categories, vec!["editor".to_string()]);
+
+    // the upgrade is written back
+    let json = tmp.conf();
+    assert_eq!(json["config"]["version"], CONFIG_VERSION);
+    assert_eq!(json["entries"][0]["host_path"], "$HOME/.vimrc");
+}
+
+#[test]
+fn load_rejects_newer_config() {
+    let tmp = TempHome::new();
+    tmp.init();
+    let mut json = tmp.conf();
+    json["config"]["version"] = serde_json::json!(CONFIG_VERSION + 1);
+    let conf = tmp.write("trove/trove.conf", &json.to_string());
+
+    assert!(Trove::load(Some(conf), tmp.home()).is_err());
+}
+
+#[test]
+fn names_are_case_sensitive_by_default() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let nvim = tmp.write(".config/nvim/init.lua", "");
+    let other = tmp.write("other/init.lua", "");
+    trove
+        .add_command(
+            &nvim,
+            &AddOptions {
+                name: Some("nvim".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+
+    assert!(trove.find_entry_by_name("NVIM").is_none());
+    trove
+        .add_command(
+            &other,
+            &AddOptions {
+                name: Some("NVIM".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(trove.entries.len(), 2);
+}
+
+#[test]
+fn case_insensitive_names_match_and_collide() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    trove.config.case_insensitive_names = true;
+    let nvim = tmp.write(".config/nvim/init.lua", "");
+    let other = tmp.write("other/init.lua", "");
+    trove
+        .add_command(
+            &nvim,
+            &AddOptions {
+                name: Some("nvim".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(trove.find_entry_by_name("NVim").unwrap().name, "nvim");
+    assert!(trove
+        .add_command(
+            &other,
+            &AddOptions {
+                name: Some("NVIM".into()),
+                ..AddOptions::default()
+            }
+        )
+        .is_err());
+    assert_eq!(trove.entries.len(), 1);
+    // the rejected file is left where it was
+    assert!(!is_symlink(&other));
+}
+
+#[test]
+fn describe_updates_without_changing_identity() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "");
+    trove
+        .add_command(
+            &vimrc,
+            &AddOptions {
+                description: Some("editor".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(tmp.conf()["entries"][0]["description"], "editor");
+
+    trove
+        .describe_command("vimrc", Some("vim settings".into()))
+        .unwrap();
+    assert_eq!(trove.entries.len(), 1);
+    let entry = trove.find_entry_by_name("vimrc").unwrap();
+    assert_eq!(entry.description.as_deref(), Some("vim settings"));
+
+    // the description plays no part in equality
+    let mut other = entry.clone();
+    other.description = None;
+    assert!(trove.entries.contains(&other));
+
+    trove.describe_command("vimrc", None).unwrap();
+    assert!(tmp.conf()["entries"][0].get("description").is_none());
+}
+
+#[test]
+fn mutated_entry_still_removes() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "");
+    trove
+        .add_command(
+            &vimrc,
+            &AddOptions {
+                categories: vec!["editor".into()],
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+
+    let mut entry = trove.find_entry_by_name("vimrc").unwrap();
+    entry.categories = vec!["something-else".into()];
+    trove.remove_entry(&entry).unwrap();
+    assert!(trove.entries.is_empty());
+    assert_eq!(tmp.conf()["entries"].as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn stored_path_can_be_shown_relative_to_store() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "");
+    let entry = trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+
+    assert_eq!(
+        trove.display_stored_path(&entry, false),
+        tmp.path("trove/store/vimrc")
+    );
+    assert_eq!(
+        trove.display_stored_path(&entry, true),
+        PathBuf::from("vimrc")
+    );
+}
+
+#[test]
+fn deploy_skips_entries_missing_from_store() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    std::fs::remove_file(tmp.path("trove/store/vimrc")).unwrap();
+
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert!(report.done.is_empty());
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(report.skipped[0].0, "vimrc");
+    assert!(!is_symlink(&vimrc));
+
+    // strict turns the skip into a failure
+    let report = trove
+        .deploy_command(
+            &None,
+            &None,
+            any,
+            &DeployOptions {
+                strict: true,
+                ..DeployOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.failed.len(), 1);
+    assert!(report.check("deploy").is_err());
+    assert!(trove
+        .deploy_command(
+            &None,
+            &Some("vimrc".into()),
+            any,
+            &DeployOptions {
+                strict: true,
+                ..DeployOptions::default()
+            }
+        )
+        .is_err());
+    assert!(!is_symlink(&vimrc));
+}
+
+fn git(dir: &PathBuf, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+}
+
+#[test]
+fn import_git_clones_and_relocates_trove() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    let repo = tmp.path("trove");
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "-q", "-m", "dotfiles"]);
+
+    let dest = tmp.path("clone");
+    let url = repo.to_string_lossy().to_string();
+    let imported = Trove::import_git(&url, &dest, tmp.home()).unwrap();
+    assert_eq!(imported.config.path, "$HOME/clone/trove.conf");
+    assert_eq!(imported.config.store_path, "$HOME/clone/store");
+    assert_eq!(
+        std::fs::read_to_string(
+            imported.stored_path(&imported.find_entry_by_name("vimrc").unwrap())
+        )
+        .unwrap(),
+        "set nu"
+    );
+}
+
+#[test]
+fn changed_since_maps_git_changes_to_entries() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    assert_eq!(trove.changed_since("HEAD").unwrap(), None);
+
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let nvim = tmp.write(".config/nvim/init.lua", "-- init");
+    tmp.write(".zshrc", "export A=1");
+    for path in [
+        vimrc,
+        nvim.parent().unwrap().to_path_buf(),
+        tmp.path(".zshrc"),
+    ] {
+        trove.add_command(&path, &AddOptions::default()).unwrap();
+    }
+    let repo = tmp.path("trove");
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "-q", "-m", "dotfiles"]);
+    tmp.write("trove/store/vimrc", "set rnu");
+    tmp.write("trove/store/nvim/lua/plugins.lua", "return {}");
+    // outside the store, so no entry changed because of it
+    tmp.write("trove/README", "notes");
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "-q", "-m", "edits"]);
+
+    assert_eq!(
+        trove.changed_since("HEAD~1").unwrap(),
+        Some(vec!["nvim".to_string(), "vimrc".to_string()])
+    );
+    assert_eq!(trove.changed_since("HEAD").unwrap(), Some(vec![]));
+    assert!(trove.changed_since("no-such-ref").is_err());
+}
+
+#[test]
+fn import_git_requires_trove_conf() {
+    let tmp = TempHome::new();
+    let repo = tmp.path("plain");
+    std::fs::create_dir_all(&repo).unwrap();
+    tmp.write("plain/README", "nothing here");
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "-q", "-m", "plain"]);
+
+    let url = repo.to_string_lossy().to_string();
+    assert!(Trove::import_git(&url, &tmp.path("clone"), tmp.home()).is_err());
+}
+
+#[test]
+fn deploy_summary_counts_linked_and_skipped() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "");
+    let zshrc = tmp.write(".zshrc", "");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    trove.add_command(&zshrc, &AddOptions::default()).unwrap();
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &Some("zshrc".into()), any, &PackOptions::default())
+        .unwrap();
+
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert_eq!(report.done, vec!["zshrc".to_string()]);
+    assert_eq!(
+        report.summary("Deployed"),
+        "Deployed 1, skipped 1 (already linked)."
+    );
+
+    std::fs::remove_file(tmp.path("trove/store/zshrc")).unwrap();
+    let report = trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    assert_eq!(report.summary("Packed"), "Packed 2.");
+    let report = trove
+        .deploy_command(
+            &None,
+            &None,
+            any,
+            &DeployOptions {
+                strict: true,
+                ..DeployOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.summary("Deployed"), "Deployed 1, 1 failed.");
+}
+
+#[test]
+fn add_follows_symlinks_by_default() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let managed = tmp.write("other/gitconfig", "[user]");
+    let link = tmp.path(".gitconfig");
+    std::os::unix::fs::symlink(&managed, &link).unwrap();
+
+    let entry = trove.add_command(&link, &AddOptions::default()).unwrap();
+    // the real file moved into the store and the other tool's link goes through it
+    assert_eq!(entry.host_path, "$HOME/other/gitconfig");
+    assert!(is_symlink(&managed));
+    assert_eq!(std::fs::read_link(&link).unwrap(), managed);
+    assert!(!is_symlink(&tmp.path("trove/store/gitconfig")));
+}
+
+#[test]
+fn add_no_follow_stores_the_link_itself() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let managed = tmp.write("other/gitconfig", "[user]");
+    let link = tmp.path(".gitconfig");
+    std::os::unix::fs::symlink(&managed, &link).unwrap();
+
+    let entry = trove
+        .add_command(
+            &link,
+            &AddOptions {
+                no_follow: true,
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(entry.host_path, "$HOME/.gitconfig");
+    // the managed file is untouched and the stored copy is the original link
+    assert!(!is_symlink(&managed));
+    let stored = tmp.path("trove/store/gitconfig");
+    assert_eq!(std::fs::read_link(&stored).unwrap(), managed);
+    assert_eq!(std::fs::read_link(&link).unwrap(), stored);
+    assert_eq!(std::fs::read_to_string(&link).unwrap(), "[user]");
+}
+
+#[test]
+fn prune_lists_then_deletes_orphans() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    tmp.write("trove/store/leftover", "");
+    tmp.write("trove/store/old-dir/file", "");
+
+    let report = trove.prune_command(false).unwrap();
+    assert!(report.done.is_empty());
+    let names: Vec<&str> = report.skipped.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(names, vec!["leftover", "old-dir"]);
+    assert!(tmp.path("trove/store/leftover").exists());
+
+    let report = trove.prune_command(true).unwrap();
+    assert_eq!(
+        report.done,
+        vec!["leftover".to_string(), "old-dir".to_string()]
+    );
+    assert!(!tmp.path("trove/store/leftover").exists());
+    assert!(!tmp.path("trove/store/old-dir").exists());
+    assert!(tmp.path("trove/store/vimrc").exists());
+    assert!(tmp.path("trove/trove.conf").exists());
+}
+
+#[test]
+fn track_links_a_file_already_in_the_store() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let stored = tmp.write("trove/store/nvim/init.lua", "vim.o.nu = true");
+    let host = tmp.path(".config/nvim/init.lua");
+
+    let entry = trove
+        .track_command(&stored, &host, &AddOptions::default())
+        .unwrap();
+    assert_eq!(entry.name, "nvim/init.lua");
+    assert_eq!(std::fs::read_link(&host).unwrap(), stored);
+    assert_eq!(trove.stored_path(&entry), stored);
+    assert_eq!(
+        tmp.conf()["entries"][0]["host_path"],
+        "$HOME/.config/nvim/init.lua"
+    );
+}
+
+#[test]
+fn track_rejects_files_outside_the_store() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write("vimrc", "set nu");
+    let host = tmp.path(".vimrc");
+
+    assert!(trove
+        .track_command(&vimrc, &host, &AddOptions::default())
+        .is_err());
+    assert!(trove
+        .track_command(
+            &tmp.path("trove/store/missing"),
+            &host,
+            &AddOptions::default()
+        )
+        .is_err());
+    assert!(!is_symlink(&host));
+    assert!(trove.entries.is_empty());
+}
+
+#[test]
+fn add_refuses_a_second_entry_for_the_same_destination() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    trove
+        .pack_command(
+            &None,
+            &Some("vimrc".into()),
+            CategoryMatch::Any,
+            &PackOptions::default(),
+        )
+        .unwrap();
+    tmp.write(".vimrc", "set rnu");
+
+    let name = Some("vimrc-work".to_string());
+    let err = trove
+        .add_command(
+            &vimrc,
+            &AddOptions {
+                name: name.clone(),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap_err();
+    assert!(matches!(
+        trove_error(err),
+        TroveError::DestinationConflict { entry, .. } if entry == "vimrc"
+    ));
+    assert!(trove.find_destination_conflicts().is_empty());
+
+    trove
+        .add_command(
+            &vimrc,
+            &AddOptions {
+                name: name.clone(),
+                allow_conflict: true,
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        trove.find_destination_conflicts(),
+        vec![("vimrc".to_string(), "vimrc-work".to_string())]
+    );
+    let report = trove.verify_command(false).unwrap();
+    assert!(report.failed.iter().any(|(n, _)| n == "vimrc"));
+    assert!(report.check("verify").is_err());
+}
+
+#[test]
+fn search_ranks_name_matches_first() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for (rel, cats) in [
+        (".vimrc", "editor"),
+        (".config/nvim", "vim"),
+        (".zshrc", "shell"),
+    ] {
+        let path = tmp.write(rel, "x");
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    categories: vec![cats.into()],
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+
+    let names = |found: Vec<Entry>| found.into_iter().map(|e| e.name).collect::<Vec<_>>();
+    assert_eq!(names(trove.search_command("VIM", false)), ["vimrc", "nvim"]);
+    assert_eq!(names(trove.search_command("shell", false)), ["zshrc"]);
+    assert!(trove.search_command("vmrc", false).is_empty());
+    assert_eq!(names(trove.search_command("vmrc", true)), ["vimrc"]);
+}
+
+#[test]
+fn fuzzy_prefers_runs_and_word_starts() {
+    assert!(fuzzy_score("nv", "nvim") > fuzzy_score("nv", "neovim"));
+    assert!(fuzzy_score("ic", "init.conf") > fuzzy_score("ic", "ibice"));
+    assert_eq!(fuzzy_score("zx", "zshrc"), None);
+}
+
+#[test]
+fn init_fails_when_a_file_occupies_the_store() {
+    let tmp = TempHome::new();
+    tmp.write("trove/store", "not a directory");
+
+    assert!(Trove::create(tmp.path("trove"), tmp.home()).is_err());
+    assert!(!tmp.path("trove/trove.conf").exists());
+}
+
+#[test]
+fn init_creates_missing_parent_directories() {
+    let tmp = TempHome::new();
+    Trove::create(tmp.path("deep/trove"), tmp.home()).unwrap();
+    assert!(tmp.path("deep/trove/store").is_dir());
+}
+
+#[test]
+fn remove_by_category_restores_every_entry() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let bashrc = tmp.write(".bashrc", "alias ll='ls -l'");
+    let zshrc = tmp.write(".zshrc", "setopt autocd");
+    let vimrc = tmp.write(".vimrc", "set nu");
+    for (path, cats) in [(&bashrc, "shell"), (&zshrc, "shell"), (&vimrc, "editor")] {
+        trove
+            .add_command(
+                path,
+                &AddOptions {
+                    categories: vec![cats.into()],
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+
+    let err = trove
+        .remove_command(&None, &Some("bashrc".into()), &Some("shell".into()))
+        .unwrap_err();
+    assert_eq!(trove_error(err), TroveError::AmbiguousCriteria);
+    let report = trove
+        .remove_command(&None, &None, &Some("shell".into()))
+        .unwrap();
+    assert_eq!(report.done, ["bashrc", "zshrc"]);
+    assert!(report.failed.is_empty());
+
+    assert!(!is_symlink(&bashrc));
+    assert!(!is_symlink(&zshrc));
+    assert_eq!(
+        std::fs::read_to_string(&bashrc).unwrap(),
+        "alias ll='ls -l'"
+    );
+    assert_eq!(std::fs::read_to_string(&zshrc).unwrap(), "setopt autocd");
+    assert!(is_symlink(&vimrc));
+    assert_eq!(trove.entries.len(), 1);
+}
+
+#[test]
+fn prune_skips_troveignore_matches() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let stored = tmp.write("trove/store/nvim/init.lua", "");
+    trove
+        .track_command(
+            &stored,
+            &tmp.path(".config/nvim/init.lua"),
+            &AddOptions::default(),
+        )
+        .unwrap();
+    tmp.write(
+        "trove/store/.troveignore",
+        "# not entries\nREADME*\n\ninstall.sh\n*.sw[op]\ndocs/README\n!LICENSE\n",
+    );
+    tmp.write("trove/store/README.md", "");
+    tmp.write("trove/store/install.sh", "");
+    tmp.write("trove/store/leftover", "");
+
+    assert_eq!(trove.store_ignore().unwrap(), ["README*", "install.sh"]);
+    let report = trove.prune_command(true).unwrap();
+    assert_eq!(report.done, ["leftover"]);
+    assert!(tmp.path("trove/store/README.md").exists());
+    assert!(tmp.path("trove/store/install.sh").exists());
+    assert!(tmp.path("trove/store/.troveignore").exists());
+    assert!(stored.exists());
+}
+
+#[test]
+fn replace_conf_symlink_switches_active_trove() {
+    let tmp = TempHome::new();
+    let first = tmp.init();
+    std::fs::create_dir_all(tmp.path("other")).unwrap();
+    let second = Trove::create(tmp.path("other"), tmp.home()).unwrap();
+
+    assert!(!second.create_conf_symlink().unwrap());
+    let old = second.replace_conf_symlink().unwrap();
+    assert_eq!(old, Some(tmp.path("trove/trove.conf")));
+    assert_eq!(
+        std::fs::read_link(tmp.path(".trove")).unwrap(),
+        tmp.path("other/trove.conf")
+    );
+
+    // a regular file is never clobbered
+    std::fs::remove_file(tmp.path(".trove")).unwrap();
+    tmp.write(".trove", "mine");
+    assert!(first.replace_conf_symlink().is_err());
+    assert_eq!(std::fs::read_to_string(tmp.path(".trove")).unwrap(), "mine");
+}
+
+#[test]
+fn errors_can_be_matched_by_variant() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+
+    let other = tmp.write("vimrc", "set rnu");
+    let err = trove
+        .add_command(&other, &AddOptions::default())
+        .unwrap_err();
+    let err = trove_error(err);
+    assert_eq!(err, TroveError::DuplicateName("vimrc".into()));
+    assert_eq!(err.exit_code(), 3);
+
+    // the deployed link resolves into the store, it is still the entry's own path
+    let err = trove
+        .add_command(&vimrc, &AddOptions::default())
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("Already added as vimrc"));
+    let err = trove_error(err);
+    assert_eq!(err, TroveError::DuplicatePath(vimrc.clone()));
+    assert_eq!(err.exit_code(), 3);
+
+    let missing = tmp.path(".missing");
+    let err = trove
+        .add_command(&missing, &AddOptions::default())
+        .unwrap_err();
+    assert_eq!(trove_error(err), TroveError::PathDoesNotExist(missing));
+
+    let err = trove.describe_command("nope", None).unwrap_err();
+    let err = trove_error(err);
+    assert_eq!(err, TroveError::NotFound("nope".into()));
+    assert_eq!(err.exit_code(), 4);
+
+    let err = trove
+        .pack_command(
+            &Some("nope".into()),
+            &None,
+            CategoryMatch::Any,
+            &PackOptions::default(),
+        )
+        .unwrap_err();
+    assert_eq!(trove_error(err), TroveError::NoEntries("nope".into()));
+    assert_eq!(TroveError::AmbiguousCriteria.exit_code(), 2);
+}
+
+// vimrc in editor, zshrc in shell, tmux.conf in both
+fn categorized() -> (TempHome, Trove) {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for (file, categories) in [
+        (".vimrc", vec!["editor"]),
+        (".zshrc", vec!["shell"]),
+        (".tmux.conf", vec!["editor", "shell"]),
+    ] {
+        let path = tmp.write(file, "");
+        let categories: Vec<String> = categories.into_iter().map(String::from).collect();
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    categories: categories.clone(),
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+    return (tmp, trove);
+}
+
+fn names_in(entries: Option<std::collections::HashSet<Entry>>) -> Vec<String> {
+    let mut names: Vec<String> = entries.unwrap().into_iter().map(|e| e.name).collect();
+    names.sort();
+    return names;
+}
+
+#[test]
+fn category_any_matches_the_union() {
+    let (_tmp, trove) = categorized();
+    let found = trove.find_entry_by_category(&"editor, shell".into(), CategoryMatch::Any);
+    assert_eq!(names_in(found), ["tmux.conf", "vimrc", "zshrc"]);
+    let found = trove.find_entry_by_category(&"editor,nope".into(), CategoryMatch::Any);
+    assert_eq!(names_in(found), ["tmux.conf", "vimrc"]);
+}
+
+#[test]
+fn category_all_matches_the_intersection() {
+    let (_tmp, trove) = categorized();
+    let found = trove.find_entry_by_category(&"editor,shell".into(), CategoryMatch::All);
+    assert_eq!(names_in(found), ["tmux.conf"]);
+    let found = trove.find_entry_by_category(&"editor".into(), CategoryMatch::All);
+    assert_eq!(names_in(found), ["tmux.conf", "vimrc"]);
+}
+
+#[test]
+fn category_without_a_match_is_none() {
+    let (_tmp, trove) = categorized();
+    assert!(trove
+        .find_entry_by_category(&"nope".into(), CategoryMatch::Any)
+        .is_none());
+    assert!(trove
+        .find_entry_by_category(&"editor,nope".into(), CategoryMatch::All)
+        .is_none());
+    assert!(trove
+        .find_entry_by_category(&"".into(), CategoryMatch::All)
+        .is_none());
+    let err = trove
+        .select_entries(
+            &Some("nope".into()),
+            &None,
+            CategoryMatch::Any,
+            &Exclude::default(),
+        )
+        .unwrap_err();
+    assert_eq!(trove_error(err), TroveError::NoEntries("nope".into()));
+}
+
+#[test]
+fn categories_merge_repeated_and_comma_forms() {
+    let repeated = vec!["shell".to_string(), "a,b".to_string()];
+    assert_eq!(merge_categories(&repeated, &None), ["shell", "a,b"]);
+    assert_eq!(
+        merge_categories(&[], &Some("shell, editor,,".into())),
+        ["shell", "editor"]
+    );
+    assert_eq!(
+        merge_categories(&repeated, &Some("editor,shell".into())),
+        ["shell", "a,b", "editor"]
+    );
+
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let categories = merge_categories(&repeated, &Some("editor".into()));
+    let entry = trove
+        .add_command(
+            &vimrc,
+            &AddOptions {
+                categories: categories.clone(),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(entry.categories, ["shell", "a,b", "editor"]);
+}
+
+#[test]
+fn redeploy_removes_links_at_previous_host_paths() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    for path in [&vimrc, &zshrc] {
+        trove.add_command(path, &AddOptions::default()).unwrap();
+    }
+    // vimrc moves, the link at its old host path is what redeploy has to clear
+    let mut vim = trove.find_entry_by_name("vimrc").unwrap();
+    vim.host_path = "$HOME/.config/vim/vimrc".into();
+    trove.entries.replace(vim);
+    trove.save().unwrap();
+
+    let report = trove
+        .redeploy_command(&None, &Some("vimrc".into()), CategoryMatch::Any)
+        .unwrap();
+    assert_eq!(report.done, ["vimrc"]);
+    assert_eq!(
+        report.notes,
+        [format!("Removed stale link at {}", vimrc.display())]
+    );
+    assert!(std::fs::symlink_metadata(&vimrc).is_err());
+    assert_eq!(
+        std::fs::read_link(tmp.path(".config/vim/vimrc")).unwrap(),
+        tmp.path("trove/store/vimrc")
+    );
+    // entries that didn't move are left linked where they were
+    assert!(is_symlink(&zshrc));
+}
+
+#[test]
+fn deploy_force_replaces_an_existing_file() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    tmp.write(".vimrc", "set local");
+
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert_eq!(report.failed.len(), 1);
+    assert!(!is_symlink(&vimrc));
+
+    let opts = DeployOptions {
+        force: true,
+        ..DeployOptions::default()
+    };
+    let report = trove.deploy_command(&None, &None, any, &opts).unwrap();
+    assert_eq!(report.done, ["vimrc"]);
+    assert_eq!(report.notes, [format!("Overwrote {}", vimrc.display())]);
+    assert_eq!(
+        std::fs::read_link(&vimrc).unwrap(),
+        tmp.path("trove/store/vimrc")
+    );
+    assert_eq!(std::fs::read_to_string(&vimrc).unwrap(), "set nu");
+}
+
+#[test]
+fn deploy_force_keeps_a_directory_unless_confirmed() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    tmp.write(".vimrc/keep", "x");
+
+    let decline = |_: &PathBuf| Ok(false);
+    for overwrite_dir in [None, Some(&decline as OverwriteDir)] {
+        let opts = DeployOptions {
+            force: true,
+            overwrite_dir,
+            ..DeployOptions::default()
+        };
+        let report = trove.deploy_command(&None, &None, any, &opts).unwrap();
+        assert_eq!(report.failed.len(), 1);
+        assert!(format!("{:#}", report.failed[0].1).contains("Refusing to overwrite directory"));
+        assert!(tmp.path(".vimrc/keep").is_file());
+    }
+
+    let accept = |_: &PathBuf| Ok(true);
+    let opts = DeployOptions {
+        force: true,
+        overwrite_dir: Some(&accept),
+        ..DeployOptions::default()
+    };
+    let report = trove.deploy_command(&None, &None, any, &opts).unwrap();
+    assert_eq!(report.done, ["vimrc"]);
+    assert!(is_symlink(&vimrc));
+}
+
+#[test]
+fn deploy_only_missing_fixes_wrong_links_and_keeps_files() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let mut paths = vec![];
+    for rel in [".vimrc", ".zshrc", ".bashrc", ".inputrc"] {
+        let path = tmp.write(rel, rel);
+        trove.add_command(&path, &AddOptions::default()).unwrap();
+        paths.push(path);
+    }
+    let [vimrc, zshrc, bashrc, inputrc] = &paths[..] else {
+        unreachable!()
+    };
+    std::fs::remove_file(zshrc).unwrap();
+    std::os::unix::fs::symlink(tmp.path("elsewhere"), zshrc).unwrap();
+    std::fs::remove_file(bashrc).unwrap();
+    std::fs::remove_file(inputrc).unwrap();
+    tmp.write(".inputrc", "mine");
+
+    let any = CategoryMatch::Any;
+    let report = trove
+        .deploy_command(
+            &None,
+            &None,
+            any,
+            &DeployOptions {
+                only_missing: true,
+                ..DeployOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        report.summary("Deployed"),
+        "Deployed 2, skipped 1 (already linked), 1 failed."
+    );
+    assert_eq!(report.failed[0].0, "inputrc");
+    assert!(report.notes[0].starts_with("Relinked"));
+    for (path, name) in [(vimrc, "vimrc"), (zshrc, "zshrc"), (bashrc, "bashrc")] {
+        assert_eq!(
+            std::fs::read_link(path).unwrap(),
+            tmp.path("trove/store").join(name)
+        );
+    }
+    assert_eq!(std::fs::read_to_string(inputrc).unwrap(), "mine");
+}
+
+#[test]
+fn broken_entries_lists_only_problems() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for rel in [".vimrc", ".zshrc", ".bashrc", ".inputrc", ".tmux.conf"] {
+        let path = tmp.write(rel, rel);
+        trove.add_command(&path, &AddOptions::default()).unwrap();
+    }
+    assert!(trove.broken_entries().is_empty());
+
+    // packed is fine, the rest are not
+    trove
+        .pack_command(
+            &None,
+            &Some("tmux.conf".into()),
+            CategoryMatch::Any,
+            &PackOptions::default(),
+        )
+        .unwrap();
+    std::fs::remove_file(tmp.path("trove/store/zshrc")).unwrap();
+    std::fs::remove_file(tmp.path(".bashrc")).unwrap();
+    std::os::unix::fs::symlink(tmp.path("gone"), tmp.path(".bashrc")).unwrap();
+    std::fs::remove_file(tmp.path(".inputrc")).unwrap();
+    tmp.write(".inputrc", "mine");
+
+    let broken: Vec<(String, &str)> = trove
+        .broken_entries()
+        .into_iter()
+        .map(|(e, reason)| (e.name, reason))
+        .collect();
+    assert_eq!(
+        broken,
+        [
+            ("bashrc".to_string(), "dangling link"),
+            ("inputrc".to_string(), "not a link"),
+            ("zshrc".to_string(), "missing from store"),
+        ]
+    );
+}
+
+#[test]
+fn discover_adds_untracked_store_files_on_load() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let tmux = tmp.write(".tmux.conf", "set -g mouse on");
+    trove
+        .add_command(
+            &tmux,
+            &AddOptions {
+                name: Some("tmux".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    trove.config.discover = true;
+    trove.save().unwrap();
+    tmp.write("trove/store/gitconfig", "[user]");
+    tmp.write("trove/store/tmux.conf", "explicit entry deploys here");
+    tmp.write("trove/store/.git/HEAD", "");
+    tmp.write("trove/store/README.md", "");
+    tmp.write("trove/store/.troveignore", "README*");
+
+    let mut trove = Trove::load(None, tmp.home()).unwrap();
+    let names: Vec<&str> = trove
+        .entries_sorted()
+        .iter()
+        .map(|e| e.name.as_str())
+        .collect();
+    assert_eq!(names, ["gitconfig", "tmux"]);
+    let git = trove.find_entry_by_name("gitconfig").unwrap();
+    assert!(git.discovered);
+    assert_eq!(git.host_path, "$HOME/.gitconfig");
+
+    // discovered entries stay out of trove.conf until they are described
+    trove.save().unwrap();
+    assert_eq!(tmp.conf()["entries"].as_array().unwrap().len(), 1);
+    trove
+        .describe_command("gitconfig", Some("git identity".into()))
+        .unwrap();
+    assert_eq!(tmp.conf()["entries"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn rfc3339_formats_utc_dates() {
+    assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+    assert_eq!(rfc3339(951782400), "2000-02-29T00:00:00Z");
+    assert_eq!(rfc3339(1709294400 + 61), "2024-03-01T12:01:01Z");
+}
+
+#[test]
+fn add_records_when_the_entry_was_added() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let entry = trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    let added = entry.added_at.clone().unwrap();
+    assert_eq!(added.len(), "1970-01-01T00:00:00Z".len());
+    assert_eq!(tmp.conf()["entries"][0]["added_at"], added.as_str());
+
+    // the timestamp is not part of an entry's identity
+    let mut older = entry.clone();
+    older.added_at = Some("1970-01-01T00:00:00Z".into());
+    assert_eq!(older, entry);
+    assert!(trove.entries.contains(&older));
+}
+
+#[test]
+fn add_records_the_origin_host() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let entry = trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    assert!(entry.origin.is_some());
+    assert_eq!(entry.origin, hostname());
+    assert_eq!(
+        tmp.conf()["entries"][0]["origin"],
+        entry.origin.clone().unwrap().as_str()
+    );
+
+    // not part of identity, and configs from before it load without one
+    let mut elsewhere = entry.clone();
+    elsewhere.origin = Some("laptop".into());
+    assert_eq!(elsewhere, entry);
+    let mut conf = tmp.conf();
+    conf["entries"][0].as_object_mut().unwrap().remove("origin");
+    std::fs::write(tmp.path("trove/trove.conf"), conf.to_string()).unwrap();
+    let trove = Trove::load(None, tmp.home()).unwrap();
+    assert_eq!(trove.find_entry_by_name("vimrc").unwrap().origin, None);
+}
+
+#[test]
+fn deploy_and_pack_into_a_target_dir() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let nvim = tmp.write(".config/nvim/init.lua", "");
+    for path in [&vimrc, &nvim] {
+        trove.add_command(path, &AddOptions::default()).unwrap();
+    }
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+
+    let target_dir = Some(tmp.path("sandbox"));
+    let opts = DeployOptions {
+        target_dir: target_dir.clone(),
+        ..DeployOptions::default()
+    };
+    let report = trove.deploy_command(&None, &None, any, &opts).unwrap();
+    assert_eq!(report.done.len(), 2);
+    assert_eq!(
+        std::fs::read_link(tmp.path("sandbox/.vimrc")).unwrap(),
+        tmp.path("trove/store/vimrc")
+    );
+    assert!(is_symlink(&tmp.path("sandbox/.config/nvim/init.lua")));
+    assert!(!vimrc.exists());
+    assert!(!nvim.exists());
+
+    let opts = PackOptions {
+        target_dir,
+        ..PackOptions::default()
+    };
+    trove.pack_command(&None, &None, any, &opts).unwrap();
+    assert!(!tmp.path("sandbox/.vimrc").exists());
+    assert!(!is_symlink(&tmp.path("sandbox/.config/nvim/init.lua")));
+}
+
+#[test]
+fn add_recursive_makes_an_entry_per_child() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    tmp.write(".config/nvim/init.lua", "vim.o.nu = true");
+    tmp.write(".config/git/config", "[user]");
+    tmp.write(".config/git/ignore", "target");
+    tmp.write(".config/cache/junk", "");
+    let config = tmp.path(".config");
+    let ignore = Some("cache".to_string());
+
+    let report = trove
+        .add_recursive_command(
+            &config,
+            1,
+            &AddOptions {
+                categories: vec!["desktop".into()],
+                ignore: ignore.clone(),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.done, ["config/git", "config/nvim"]);
+    assert!(config.is_dir() && !is_symlink(&config));
+    assert_eq!(
+        std::fs::read_link(tmp.path(".config/nvim")).unwrap(),
+        tmp.path("trove/store/config/nvim")
+    );
+    assert!(!is_symlink(&tmp.path(".config/cache")));
+    let nvim = trove.find_entry_by_name("config/nvim").unwrap();
+    assert_eq!(nvim.categories, ["desktop"]);
+    assert_eq!(nvim.host_path, "$HOME/.config/nvim");
+}
+
+#[test]
+fn add_recursive_walks_down_to_depth() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    tmp.write("dots/git/config", "[user]");
+    tmp.write("dots/git/ignore", "target");
+    tmp.write("dots/profile", "");
+
+    let report = trove
+        .add_recursive_command(
+            &tmp.path("dots"),
+            2,
+            &AddOptions {
+                name: Some("d".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.done, ["d/git/config", "d/git/ignore", "d/profile"]);
+    assert!(is_symlink(&tmp.path("dots/git/config")));
+    assert!(!is_symlink(&tmp.path("dots/git")));
+    assert_eq!(
+        std::fs::read_to_string(tmp.path("trove/store/d/git/ignore")).unwrap(),
+        "target"
+    );
+}
+
+#[test]
+fn default_categories_are_merged_after_explicit_ones() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    trove.config.default_categories = vec!["personal".into(), "shell".into()];
+    trove.save().unwrap();
+    let mut trove = Trove::load(None, tmp.home()).unwrap();
+    assert_eq!(trove.config.default_categories, ["personal", "shell"]);
+
+    let categories = trove.with_default_categories(&["shell".into(), "zsh".into()]);
+    assert_eq!(categories, ["shell", "zsh", "personal"]);
+    let zshrc = tmp.write(".zshrc", "");
+    let entry = trove
+        .add_command(
+            &zshrc,
+            &AddOptions {
+                categories: categories.clone(),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(entry.categories, ["shell", "zsh", "personal"]);
+}
+
+#[test]
+fn shell_quoting_survives_spaces_and_quotes() {
+    assert_eq!(shell_quote("/home/bob/my trove"), "'/home/bob/my trove'");
+    assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    assert_eq!(fish_quote("it's a \\ path"), "'it\\'s a \\\\ path'");
+
+    let tricky = "/tmp/a b/it's $HOME `x`";
+    let out = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "export X={}; printf %s \"$X\"",
+            shell_quote(tricky)
+        ))
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), tricky);
+}
+
+#[test]
+fn excluded_entries_are_left_out_of_deploy_and_pack() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for (rel, cats) in [
+        (".vimrc", "editor"),
+        (".zshrc", "shell"),
+        (".bashrc", "shell"),
+        (".inputrc", "misc"),
+    ] {
+        let path = tmp.write(rel, "");
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    categories: vec![cats.into()],
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+    let any = CategoryMatch::Any;
+    let opts = PackOptions {
+        exclude: Exclude {
+            names: vec!["vimrc".into(), "missing".into()],
+            categories: vec!["shell".into()],
+        },
+        ..PackOptions::default()
+    };
+    let report = trove.pack_command(&None, &None, any, &opts).unwrap();
+    assert_eq!(report.done, ["inputrc"]);
+    assert!(is_symlink(&tmp.path(".vimrc")));
+    assert!(is_symlink(&tmp.path(".zshrc")));
+
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    let mut opts = DeployOptions::default();
+    opts.exclude.names = vec!["bashrc".into()];
+    let report = trove
+        .deploy_command(&Some("shell".into()), &None, any, &opts)
+        .unwrap();
+    assert_eq!(report.done, ["zshrc"]);
+    assert!(!tmp.path(".bashrc").exists());
+}
+
+#[test]
+fn status_counts_deployed_entries_and_categories() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for (rel, cats) in [
+        (".vimrc", vec!["editor", "cli"]),
+        (".zshrc", vec!["cli"]),
+        (".inputrc", vec![]),
+    ] {
+        let path = tmp.write(rel, "");
+        let cats: Vec<String> = cats.into_iter().map(String::from).collect();
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    categories: cats.clone(),
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+    trove
+        .pack_command(
+            &None,
+            &Some("zshrc".into()),
+            CategoryMatch::Any,
+            &PackOptions::default(),
+        )
+        .unwrap();
+    std::fs::remove_file(tmp.path("trove/store/inputrc")).unwrap();
+
+    let deployed: Vec<String> = trove
+        .select_entries(&None, &None, CategoryMatch::Any, &Exclude::default())
+        .unwrap()
+        .into_iter()
+        .filter(|e| trove.is_deployed(e))
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(deployed, ["vimrc"]);
+    assert_eq!(
+        trove.category_counts(),
+        [("cli".to_string(), 2), ("editor".to_string(), 1)]
+    );
+}
+
+#[test]
+fn config_can_live_outside_the_trove_dir() {
+    let tmp = TempHome::new();
+    std::fs::create_dir_all(tmp.path("repo")).unwrap();
+    let conf = tmp.path(".config/trove/config.json");
+    let trove = Trove::create_with_config(tmp.path("repo"), conf.clone(), tmp.home()).unwrap();
+    assert!(trove.create_conf_symlink().unwrap());
+
+    assert!(conf.is_file());
+    assert!(!tmp.path("repo/trove.conf").exists());
+    assert!(tmp.path("repo/store").is_dir());
+    assert_eq!(std::fs::read_link(tmp.path(".trove")).unwrap(), conf);
+
+    let mut trove = Trove::load(None, tmp.home()).unwrap();
+    assert_eq!(trove.config.path, "$HOME/.config/trove/config.json");
+    assert_eq!(trove.config.store_path, "$HOME/repo/store");
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    assert!(tmp.path("repo/store/vimrc").is_file());
+    assert!(tmp.path(".config/trove/history").is_file());
+}
+
+#[test]
+fn doctor_relinks_and_cleans_up_what_it_safely_can() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for rel in [".vimrc", ".zshrc", ".bashrc", ".inputrc", ".tmux.conf"] {
+        let path = tmp.write(rel, "x");
+        trove.add_command(&path, &AddOptions::default()).unwrap();
+    }
+    // packed, deleted from the store twice over, and replaced by a real file
+    std::fs::remove_file(tmp.path(".zshrc")).unwrap();
+    std::fs::remove_file(tmp.path("trove/store/bashrc")).unwrap();
+    std::fs::remove_file(tmp.path("trove/store/inputrc")).unwrap();
+    std::fs::remove_file(tmp.path(".tmux.conf")).unwrap();
+    tmp.write(".tmux.conf", "local");
+
+    let report = trove
+        .doctor_command(&tmp.path("trove/trove.conf"), &|e, _| {
+            return Ok(e.name == "bashrc");
+        })
+        .unwrap();
+    assert_eq!(report.done, vec!["bashrc".to_string(), "zshrc".to_string()]);
+    let failed: Vec<&str> = report.failed.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(failed, vec!["inputrc", "tmux.conf"]);
+
+    assert!(is_symlink(&tmp.path(".zshrc")));
+    assert!(tmp.path(".zshrc").exists());
+    assert!(!is_symlink(&tmp.path(".bashrc")));
+    assert!(is_symlink(&tmp.path(".inputrc")));
+    assert_eq!(
+        std::fs::read_to_string(tmp.path(".tmux.conf")).unwrap(),
+        "local"
+    );
+}
+
+#[test]
+fn doctor_repoints_a_stale_home_link() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    std::fs::rename(tmp.path("trove"), tmp.path("dotfiles")).unwrap();
+    assert!(Trove::load(None, tmp.home()).is_err());
+
+    let conf = tmp.path("dotfiles/trove.conf");
+    let mut trove = Trove::load(Some(conf.clone()), tmp.home()).unwrap();
+    let report = trove.doctor_command(&conf, &|_, _| Ok(false)).unwrap();
+    assert_eq!(report.done.len(), 2);
+    assert!(report.failed.is_empty());
+
+    let trove = Trove::load(None, tmp.home()).unwrap();
+    assert_eq!(trove.config.store_path, "$HOME/dotfiles/store");
+    assert_eq!(
+        std::fs::read_link(tmp.path(".vimrc")).unwrap(),
+        tmp.path("dotfiles/store/vimrc")
+    );
+}
+
+// stands in for age, "encrypting" with rot13 so tests don't need the real binary
+fn fake_age(tmp: &TempHome) {
+    let bin = tmp.write(
+        "bin/age",
+        "#!/bin/sh\nmode=$1\nout=\nwhile [ $# -gt 1 ]; do\n  case $1 in -o) out=$2; shift ;; esac\n  shift\ndone\nif [ -n \"$out\" ]; then tr a-z n-za-m < \"$1\" > \"$out\"; else tr a-z n-za-m < \"$1\"; fi\n",
+    );
+    std::process::Command::new("chmod")
+        .arg("+x")
+        .arg(&bin)
+        .status()
+        .unwrap();
+    let path = std::env::var("PATH").unwrap_or_default();
+    std::env::set_var(
+        "PATH",
+        format!("{}:{}", bin.parent().unwrap().display(), path),
+    );
+}
+
+#[test]
+fn encrypted_entries_store_ciphertext_and_deploy_plaintext() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    fake_age(&tmp);
+    let ssh = tmp.write(".ssh/config", "host secret");
+
+    let opts = AddOptions {
+        name: Some("ssh".into()),
+        ..AddOptions::default()
+    };
+    // TROVE_KEY is process wide, so the missing key case runs in this test too
+    std::env::set_var("TROVE_KEY", tmp.path("nokey"));
+    let err = trove.encrypt_command(&ssh, &opts).unwrap_err();
+    assert!(err.to_string().contains("No key for encrypted entries"));
+    assert!(trove.find_entry_by_name("ssh").is_none());
+
+    std::env::set_var("TROVE_KEY", tmp.write("key.txt", "AGE-SECRET-KEY-1"));
+    let entry = trove.encrypt_command(&ssh, &opts).unwrap();
+    assert!(entry.encrypted);
+    assert!(entry.content_hash.is_some());
+    assert_eq!(
+        std::fs::read_to_string(tmp.path("trove/store/ssh")).unwrap(),
+        "ubfg frperg"
+    );
+    assert!(!is_symlink(&ssh));
+    assert!(tmp.conf()["entries"][0]["encrypted"].as_bool().unwrap());
+
+    let name = Some("ssh".to_string());
+    trove
+        .pack_command(&None, &name, CategoryMatch::Any, &PackOptions::default())
+        .unwrap();
+    assert!(!ssh.exists());
+    trove
+        .deploy_command(&None, &name, CategoryMatch::Any, &DeployOptions::default())
+        .unwrap()
+        .check("deploy")
+        .unwrap();
+    assert!(!is_symlink(&ssh));
+    assert_eq!(std::fs::read_to_string(&ssh).unwrap(), "host secret");
+    assert!(trove.verify_command(false).unwrap().failed.is_empty());
+
+    // local edits are not thrown away by a pack
+    std::fs::write(&ssh, "host edited").unwrap();
+    let err = trove
+        .pack_command(&None, &name, CategoryMatch::Any, &PackOptions::default())
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("changes that are not in the store"));
+    assert!(ssh.exists());
+
+    std::env::set_var("TROVE_KEY", tmp.path("nokey"));
+    std::fs::remove_file(&ssh).unwrap();
+    let report = trove
+        .deploy_command(&None, &None, CategoryMatch::Any, &DeployOptions::default())
+        .unwrap();
+    assert!(report.failed[0].1.contains("No key for encrypted entries"));
+}
+
+#[test]
+fn json_results_keep_their_field_names() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove
+        .add_command(
+            &vimrc,
+            &AddOptions {
+                categories: vec!["editor".into()],
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+
+    let summary = serde_json::to_value(trove.status_summary()).unwrap();
+    assert_eq!(summary["entries"], 1);
+    assert_eq!(summary["deployed"], 1);
+    assert_eq!(summary["broken"], 0);
+    assert_eq!(
+        summary["categories"],
+        serde_json::json!([{ "name": "editor", "entries": 1 }])
+    );
+    assert_eq!(
+        summary["store"].as_str().unwrap(),
+        tmp.path("trove/store").to_str().unwrap()
+    );
+
+    let entry = trove
+        .describe_command("vimrc", Some("shared editor settings".into()))
+        .unwrap();
+    let listing = serde_json::to_value(trove.entry_listing(&entry, true)).unwrap();
+    assert_eq!(listing["name"], "vimrc");
+    assert_eq!(listing["store_path"], "vimrc");
+    assert_eq!(listing["host_path"], "$HOME/.vimrc");
+    assert_eq!(listing["categories"], serde_json::json!(["editor"]));
+    assert_eq!(listing["deployed"], true);
+    assert_eq!(listing["description"], "shared editor settings");
+    assert!(listing["added_at"].is_string());
+
+    std::fs::remove_file(tmp.path("trove/store/vimrc")).unwrap();
+    let result = VerifyResult::from(&trove.verify_command(false).unwrap());
+    assert_eq!(
+        serde_json::to_value(result).unwrap(),
+        serde_json::json!({
+            "ok": [],
+            "problems": [{ "name": "vimrc", "reason": "missing from store" }],
+        })
+    );
+}
+
+#[test]
+fn store_and_link_moves_the_file_back_when_linking_fails() {
+    let tmp = TempHome::new();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    std::fs::create_dir_all(tmp.path("store")).unwrap();
+    let stored = tmp.path("store/vimrc");
+
+    let err = store_and_link(&vimrc, &stored, &|_, _| {
+        return Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+    })
+    .unwrap_err();
+    assert!(err.to_string().contains("Could not link"));
+    assert!(!is_symlink(&vimrc));
+    assert_eq!(std::fs::read_to_string(&vimrc).unwrap(), "set nu");
+    assert!(!stored.exists());
+}
+
+#[test]
+fn add_forgets_the_entry_when_the_file_cannot_be_stored() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    // a file where the entry's store subdirectory would go
+    tmp.write("trove/store/nvim", "x");
+    let init = tmp.write(".config/nvim/init.lua", "vim.o.nu = true");
+
+    let name = Some("nvim/init.lua".to_string());
+    assert!(trove
+        .add_command(
+            &init,
+            &AddOptions {
+                name: name.clone(),
+                ..AddOptions::default()
+            }
+        )
+        .is_err());
+    assert!(trove.find_entry_by_name("nvim/init.lua").is_none());
+    assert!(Trove::load(None, tmp.home()).unwrap().entries.is_empty());
+    assert_eq!(std::fs::read_to_string(&init).unwrap(), "vim.o.nu = true");
+}
+
+#[test]
+fn store_name_decouples_the_store_file_from_the_entry_name() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let gitconfig = tmp.write(".gitconfig", "[user]");
+    let name = Some("work-gitconfig".to_string());
+    let entry = trove
+        .add_command(
+            &gitconfig,
+            &AddOptions {
+                name: name.clone(),
+                store_name: Some("gitconfig".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(entry.stored_name(), "gitconfig");
+    assert!(tmp.path("trove/store/gitconfig").is_file());
+    assert_eq!(
+        std::fs::read_link(&gitconfig).unwrap(),
+        tmp.path("trove/store/gitconfig")
+    );
+    assert_eq!(tmp.conf()["entries"][0]["store_name"], "gitconfig");
+
+    // a second entry can't take the same store file
+    let other = tmp.write("work/.gitconfig", "[user]");
+    let err = trove
+        .add_command(
+            &other,
+            &AddOptions {
+                store_name: Some("gitconfig".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("already stored as gitconfig"));
+
+    let mut trove = Trove::load(None, tmp.home()).unwrap();
+    assert!(trove.verify_command(false).unwrap().failed.is_empty());
+    assert!(trove.prune_command(false).unwrap().done.is_empty());
+    trove
+        .pack_command(&None, &name, CategoryMatch::Any, &PackOptions::default())
+        .unwrap();
+    trove
+        .deploy_command(&None, &name, CategoryMatch::Any, &DeployOptions::default())
+        .unwrap();
+    assert!(is_symlink(&gitconfig));
+
+    trove.remove_command(&None, &name, &None).unwrap();
+    assert!(!is_symlink(&gitconfig));
+    assert!(!tmp.path("trove/store/gitconfig").exists());
+    trove.undo_command().unwrap();
+    assert!(tmp.path("trove/store/gitconfig").is_file());
+}
+
+#[test]
+fn interactive_selection_groups_and_parses_picks() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for (rel, cats) in [
+        (".zshrc", vec!["shell"]),
+        (".vimrc", vec!["vim", "editor"]),
+        (".bashrc", vec!["shell"]),
+        (".inputrc", vec![]),
+    ] {
+        let path = tmp.write(rel, "x");
+        let cats: Vec<String> = cats.into_iter().map(String::from).collect();
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    categories: cats.clone(),
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+    let entries = trove
+        .select_entries(&None, &None, CategoryMatch::Any, &Exclude::default())
+        .unwrap();
+    let groups = group_by_category(&entries);
+    let shape: Vec<(Option<&str>, Vec<&str>)> = groups
+        .iter()
+        .map(|(c, es)| (c.as_deref(), es.iter().map(|e| e.name.as_str()).collect()))
+        .collect();
+    assert_eq!(
+        shape,
+        vec![
+            (Some("editor"), vec!["vimrc"]),
+            (Some("shell"), vec!["bashrc", "zshrc"]),
+            (None, vec!["inputrc"]),
+        ]
+    );
+
+    let numbered: Vec<Entry> = groups.into_iter().flat_map(|(_, es)| es).collect();
+    assert_eq!(
+        parse_selection("1, 3-4 ,bashrc", &numbered).unwrap(),
+        vec!["vimrc", "zshrc", "inputrc", "bashrc"]
+    );
+    assert!(parse_selection("", &numbered).unwrap().is_empty());
+    assert!(parse_selection("5", &numbered).is_err());
+    assert!(parse_selection("nvim", &numbered).is_err());
+}
+
+#[test]
+fn config_set_validates_and_round_trips() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    trove.config.set("discover", "true").unwrap();
+    trove.config.set("ignore", "node_modules, .git").unwrap();
+    trove.config.set("default_categories", "linux").unwrap();
+    trove.save().unwrap();
+
+    let conf = tmp.conf();
+    assert_eq!(conf["config"]["discover"], true);
+    assert_eq!(
+        conf["config"]["ignore"],
+        serde_json::json!(["node_modules", ".git"])
+    );
+
+    // printed values can be fed back to set unchanged
+    let mut config = Trove::load_config(None, &tmp.home()).unwrap();
+    for (key, value) in config.settings() {
+        config.set(key, &value).unwrap();
+    }
+    assert_eq!(config.ignore, vec!["node_modules", ".git"]);
+    assert_eq!(config.default_categories, vec!["linux"]);
+
+    let err = config.set("store_path", "/tmp").unwrap_err();
+    assert!(err.to_string().contains("expected one of: ignore"));
+    assert!(config.set("discover", "yes").is_err());
+    assert!(config.discover);
+}
+
+#[test]
+fn add_links_a_directory_when_nothing_in_it_is_ignored() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    trove.config.set("ignore", "node_modules").unwrap();
+    tmp.write(".config/nvim/init.lua", "vim.o.nu = true");
+    let nvim = tmp.path(".config/nvim");
+
+    let entry = trove.add_command(&nvim, &AddOptions::default()).unwrap();
+    assert!(entry.ignore.is_empty());
+    assert!(entry.is_linked());
+    assert!(is_symlink(&nvim));
+    assert!(tmp.path("trove/store/nvim/init.lua").is_file());
+}
+
+#[test]
+fn stats_add_up_store_sizes_per_entry() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    tmp.write(".config/nvim/init.lua", &"x".repeat(300));
+    tmp.write(".config/nvim/lua/plugins.lua", &"x".repeat(200));
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let zshrc = tmp.write(".zshrc", &"x".repeat(100));
+    for (path, cats) in [
+        (tmp.path(".config/nvim"), vec!["editor".to_string()]),
+        (vimrc, vec!["editor".to_string()]),
+        (zshrc, vec![]),
+    ] {
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    categories: cats.clone(),
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+    trove
+        .pack_command(
+            &None,
+            &Some("zshrc".into()),
+            CategoryMatch::Any,
+            &PackOptions::default(),
+        )
+        .unwrap();
+
+    let stats = trove.stats_command(2);
+    assert_eq!(stats.entries, 3);
+    assert_eq!(stats.deployed, 2);
+    assert_eq!(stats.store_bytes, 606);
+    assert_eq!(stats.files, 4);
+    let largest: Vec<(&str, u64)> = stats
+        .largest
+        .iter()
+        .map(|e| (e.name.as_str(), e.bytes))
+        .collect();
+    assert_eq!(largest, vec![("nvim", 500), ("zshrc", 100)]);
+    assert_eq!(stats.categories[0].name, "editor");
+    assert_eq!(stats.categories[0].entries, 2);
+
+    std::fs::remove_file(tmp.path("trove/store/zshrc")).unwrap();
+    assert_eq!(trove.stats_command(5).store_bytes, 506);
+}
+
+#[test]
+fn replace_only_takes_over_stale_entries() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    assert!(trove.replace_stale_entry("vimrc").is_err());
+
+    // store file gone and only a dangling link left behind
+    std::fs::remove_file(tmp.path("trove/store/vimrc")).unwrap();
+    assert!(is_symlink(&vimrc));
+    let vimrc_new = tmp.write(".config/vim/vimrc", "set rnu");
+    let err = trove
+        .add_command(&vimrc_new, &AddOptions::default())
+        .unwrap_err();
+    assert!(matches!(trove_error(err), TroveError::DuplicateName(n) if n == "vimrc"));
+
+    let old = trove.replace_stale_entry("vimrc").unwrap().unwrap();
+    assert_eq!(old.host_path, "$HOME/.vimrc");
+    assert!(!is_symlink(&vimrc));
+    let entry = trove
+        .add_command(&vimrc_new, &AddOptions::default())
+        .unwrap();
+    assert_eq!(entry.host_path, "$HOME/.config/vim/vimrc");
+    assert!(trove.replace_stale_entry("nvim").unwrap().is_none());
+}
+
+#[test]
+fn log_lists_journaled_operations_newest_first() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    assert!(trove.log_command(None).unwrap().is_empty());
+    for rel in [".vimrc", ".zshrc"] {
+        let path = tmp.write(rel, "x");
+        trove.add_command(&path, &AddOptions::default()).unwrap();
+    }
+    trove
+        .remove_command(&None, &Some("vimrc".into()), &None)
+        .unwrap();
+
+    let log: Vec<(String, String)> = trove
+        .log_command(None)
+        .unwrap()
+        .iter()
+        .map(|r| (format!("{:?}", r.operation), r.entry.name.clone()))
+        .collect();
+    let expected = [("Remove", "vimrc"), ("Add", "zshrc"), ("Add", "vimrc")];
+    assert_eq!(log, expected.map(|(o, n)| (o.to_string(), n.to_string())));
+    assert_eq!(trove.log_command(Some(1)).unwrap().len(), 1);
+    let record = &trove.log_command(Some(1)).unwrap()[0];
+    assert_eq!(record.host_path, "$HOME/.vimrc");
+    assert_eq!(record.store_path, "$HOME/trove/store/vimrc");
+
+    // one json object per line
+    let journal = std::fs::read_to_string(tmp.path("trove/history")).unwrap();
+    assert_eq!(journal.lines().count(), 3);
+    for line in journal.lines() {
+        serde_json::from_str::<serde_json::Value>(line).unwrap();
+    }
+}
+
+#[test]
+fn deploy_carries_on_past_unusable_host_paths() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let conf = tmp.write(".config/app/conf", "x");
+    let zshrc = tmp.write(".zshrc", "x");
+    for path in [&vimrc, &conf, &zshrc] {
+        trove.add_command(path, &AddOptions::default()).unwrap();
+    }
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    // the entry's parent directory turned into a file, and a path that can't resolve
+    std::fs::remove_dir_all(tmp.path(".config/app")).unwrap();
+    tmp.write(".config/app", "not a dir");
+    std::env::remove_var("DOT_TROVE_TEST_NO_SUCH_DIR");
+    let mut zsh = trove.find_entry_by_name("zshrc").unwrap();
+    zsh.host_path = "$DOT_TROVE_TEST_NO_SUCH_DIR/.zshrc".into();
+    trove.entries.replace(zsh);
+
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert_eq!(report.done, vec!["vimrc".to_string()]);
+    assert_eq!(report.failed.len(), 2);
+    assert_eq!(report.failed[0].0, "conf");
+    assert!(report.failed[0]
+        .1
+        .contains(".config/app is not a directory"));
+    assert_eq!(report.failed[1].0, "zshrc");
+    assert!(report.failed[1]
+        .1
+        .contains("does not resolve to an absolute path"));
+    assert!(is_symlink(&vimrc));
+
+    let err = trove
+        .deploy_command(&None, &Some("conf".into()), any, &DeployOptions::default())
+        .unwrap_err();
+    assert!(err.to_string().contains("is not a directory"));
+
+    let report = trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    assert_eq!(report.done, vec!["vimrc".to_string()]);
+    assert_eq!(report.skipped.len(), 2);
+}
+
+#[test]
+fn trailing_slashes_name_the_same_host_path() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    tmp.write("somedir/a", "x");
+    trove
+        .add_entry(tmp.path("somedir/"), &AddOptions::default())
+        .unwrap();
+    assert_eq!(tmp.conf()["entries"][0]["host_path"], "$HOME/somedir");
+
+    let err = trove
+        .add_entry(
+            tmp.path("somedir"),
+            &AddOptions {
+                name: Some("other".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap_err();
+    assert!(matches!(
+        trove_error(err),
+        TroveError::DestinationConflict { entry, .. } if entry == "somedir"
+    ));
+    for p in ["somedir", "somedir/", "somedir/.", "x/../somedir/"] {
+        let found = trove.find_entry_by_path(&tmp.path(p)).map(|e| e.name);
+        assert_eq!(found.as_deref(), Some("somedir"), "{}", p);
+    }
+}
+
+#[test]
+fn clean_removes_dangling_store_links_no_entry_deploys_to() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let zshrc = tmp.write(".zshrc", "x");
+    for path in [&vimrc, &zshrc] {
+        trove.add_command(path, &AddOptions::default()).unwrap();
+    }
+    // vimrc now deploys elsewhere, and an old entry's link was left dangling
+    let mut vim = trove.find_entry_by_name("vimrc").unwrap();
+    vim.host_path = "$HOME/.config/vim/vimrc".into();
+    trove.entries.replace(vim);
+    symlink::symlink_file(tmp.path("trove/store/gone"), tmp.path(".oldrc")).unwrap();
+    symlink::symlink_file(tmp.path("elsewhere"), tmp.path(".otherrc")).unwrap();
+    // a link of the user's own into the store, not made by any entry
+    symlink::symlink_file(tmp.path("trove/store/zshrc"), tmp.path(".zshrc.local")).unwrap();
+
+    let report = trove.clean_links(&None).unwrap();
+    assert_eq!(report.done, vec![tmp.path(".oldrc").display().to_string()]);
+    assert!(is_symlink(&vimrc));
+    assert!(is_symlink(&zshrc));
+    assert!(is_symlink(&tmp.path(".otherrc")));
+    assert!(is_symlink(&tmp.path(".zshrc.local")));
+    assert!(tmp.path("trove/store/vimrc").is_file());
+}
+
+#[test]
+fn dir_links_setting_covers_file_and_directory_entries() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    tmp.write(".config/nvim/init.lua", "vim.o.nu = true");
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    let nvim = tmp.path(".config/nvim");
+    for path in [&zshrc, &nvim] {
+        trove.add_command(path, &AddOptions::default()).unwrap();
+    }
+    let any = CategoryMatch::Any;
+
+    for kind in ["symlink", "junction", "auto"] {
+        trove.config.set("dir_links", kind).unwrap();
+        trove
+            .pack_command(&None, &None, any, &PackOptions::default())
+            .unwrap();
+        assert!(!nvim.exists());
+        let report = trove
+            .deploy_command(&None, &None, any, &DeployOptions::default())
+            .unwrap();
+        assert_eq!(report.done.len(), 2, "{}", kind);
+
+        // the file still gets a file link, the directory the configured kind
+        assert!(is_symlink(&zshrc));
+        assert_eq!(std::fs::read_to_string(&zshrc).unwrap(), "export A=1");
+        assert!(nvim.is_dir());
+        assert_eq!(
+            std::fs::read_to_string(nvim.join("init.lua")).unwrap(),
+            "vim.o.nu = true"
+        );
+        assert_eq!(
+            std::fs::read_link(&nvim).unwrap(),
+            tmp.path("trove/store/nvim")
+        );
+    }
+
+    assert!(trove.config.set("dir_links", "hardlink").is_err());
+    trove.config.set("dir_links", "junction").unwrap();
+    trove.save().unwrap();
+    assert_eq!(tmp.conf()["config"]["dir_links"], "junction");
+}
+
+#[test]
+fn verify_compares_store_links_lexically_by_default() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    // the store lives on another volume, reached through a link in the trove dir
+    std::fs::create_dir_all(tmp.path("vol")).unwrap();
+    std::fs::rename(tmp.path("trove/store"), tmp.path("vol/store")).unwrap();
+    symlink::symlink_dir(tmp.path("vol/store"), tmp.path("trove/store")).unwrap();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let real = tmp.write("dotfiles/bashrc", "alias ll='ls -l'");
+    let bashrc = tmp.path(".bashrc");
+    symlink::symlink_file(&real, &bashrc).unwrap();
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    trove
+        .add_command(
+            &bashrc,
+            &AddOptions {
+                no_follow: true,
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+
+    let report = trove.verify_command(false).unwrap();
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+
+    // resolving the stored symlink lands outside the store
+    let report = trove.verify_command(true).unwrap();
+    assert_eq!(
+        report.failed,
+        vec![(
+            "bashrc".to_string(),
+            format!("points outside store, at {}", real.display())
+        )]
+    );
+}
+
+#[test]
+fn verify_follow_symlinks_resolves_a_linked_store() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    std::fs::create_dir_all(tmp.path("vol")).unwrap();
+    std::fs::rename(tmp.path("trove/store"), tmp.path("vol/store")).unwrap();
+    symlink::symlink_dir(tmp.path("vol/store"), tmp.path("trove/store")).unwrap();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    for path in [&vimrc, &zshrc] {
+        trove.add_command(path, &AddOptions::default()).unwrap();
+    }
+    // relinked by hand at the store's real location rather than the configured path
+    std::fs::remove_file(&zshrc).unwrap();
+    symlink::symlink_file(tmp.path("vol/store/zshrc"), &zshrc).unwrap();
+
+    let report = trove.verify_command(false).unwrap();
+    assert_eq!(report.done, ["vimrc"]);
+    assert_eq!(
+        report.failed,
+        vec![(
+            "zshrc".to_string(),
+            format!(
+                "points outside store, at {}",
+                tmp.path("vol/store/zshrc").display()
+            )
+        )]
+    );
+
+    let report = trove.verify_command(true).unwrap();
+    assert_eq!(report.done, ["vimrc", "zshrc"]);
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+}
+
+#[test]
+fn rename_category_retags_and_dedups() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    trove.config.default_categories = vec!["editor".into()];
+    for (rel, cats) in [
+        (".vimrc", vec!["editor", "vim"]),
+        (".emacs", vec!["editors", "editor"]),
+        (".zshrc", vec!["shell"]),
+    ] {
+        let path = tmp.write(rel, "");
+        let cats: Vec<String> = cats.into_iter().map(String::from).collect();
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    categories: cats.clone(),
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+
+    let renamed = trove.rename_category_command("editor", "editors").unwrap();
+    assert_eq!(renamed, vec!["emacs", "vimrc"]);
+    let mut trove = Trove::load(None, tmp.home()).unwrap();
+    let categories = |name: &str| trove.find_entry_by_name(name).unwrap().categories;
+    assert_eq!(categories("vimrc"), vec!["editors", "vim"]);
+    assert_eq!(categories("emacs"), vec!["editors"]);
+    assert_eq!(categories("zshrc"), vec!["shell"]);
+    assert_eq!(trove.config.default_categories, vec!["editors"]);
+
+    let err = trove.rename_category_command("editor", "x").unwrap_err();
+    assert!(matches!(trove_error(err), TroveError::NoEntries(c) if c == "editor"));
+}
+
+#[test]
+fn registry_names_troves_for_loading() {
+    let tmp = TempHome::new();
+    let home = tmp.home();
+    let mut personal = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    personal
+        .add_command(&vimrc, &AddOptions::default())
+        .unwrap();
+    std::fs::create_dir_all(tmp.path("work")).unwrap();
+    Trove::create(tmp.path("work"), home.clone()).unwrap();
+
+    let mut registry = Registry::load(&home).unwrap();
+    assert!(registry.troves.is_empty());
+    registry
+        .register("personal", &tmp.path("trove/trove.conf"), &home, false)
+        .unwrap();
+    registry
+        .register("work", &tmp.path("work/trove.conf"), &home, false)
+        .unwrap();
+    let err = registry
+        .register("work", &tmp.path("trove/trove.conf"), &home, false)
+        .unwrap_err();
+    assert!(err.to_string().contains("pass --force"));
+    assert!(registry
+        .register("notes", &tmp.path(".vimrc"), &home, false)
+        .is_err());
+    registry.save(&home).unwrap();
+
+    // paths are kept relative to $HOME like trove.conf's own
+    let registry = Registry::load(&home).unwrap();
+    assert_eq!(registry.troves["work"], "$HOME/work/trove.conf");
+    assert_eq!(registry.default_name(&home), Some("personal".to_string()));
+
+    let work = Trove::load(Some(registry.resolve("work", &home).unwrap()), home.clone()).unwrap();
+    assert!(work.entries.is_empty());
+    let personal = Trove::load(
+        Some(registry.resolve("personal", &home).unwrap()),
+        home.clone(),
+    )
+    .unwrap();
+    assert!(personal.find_entry_by_name("vimrc").is_some());
+
+    let err = registry.resolve("play", &home).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "No trove registered as play, known troves: personal, work."
+    );
+}
+
+#[test]
+fn check_drift_compares_copies_with_the_recorded_hash() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    tmp.write(".config/nvim/init.lua", "vim.o.nu = true");
+    tmp.write(".config/nvim/debug.log", "");
+    let vimrc = tmp.write(".vimrc", "set nu");
+    trove
+        .add_command(
+            &tmp.path(".config/nvim"),
+            &AddOptions {
+                ignore: Some("*.log".into()),
+                ..AddOptions::default()
+            },
+        )
+        .unwrap();
+    trove.add_command(&vimrc, &AddOptions::default()).unwrap();
+    let recorded = trove.find_entry_by_name("nvim").unwrap().content_hash;
+    assert!(recorded.unwrap().starts_with("fnv1a64:"));
+    assert_eq!(
+        trove.find_entry_by_name("vimrc").unwrap().content_hash,
+        None
+    );
+
+    let report = trove.check_drift_command(false).unwrap();
+    assert_eq!(report.done, vec!["nvim"]);
+    assert_eq!(
+        report.skipped,
+        vec![(
+            "vimrc".to_string(),
+            "linked (no drift possible)".to_string()
+        )]
+    );
+
+    // ignored files don't count, edits to copied ones do
+    tmp.write(".config/nvim/debug.log", "noise");
+    assert!(trove.check_drift_command(false).unwrap().failed.is_empty());
+    tmp.write(".config/nvim/init.lua", "vim.o.nu = false");
+    let report = trove.check_drift_command(false).unwrap();
+    assert_eq!(
+        report.failed,
+        vec![(
+            "nvim".to_string(),
+            format!("drifted at {}", tmp.path(".config/nvim").display())
+        )]
+    );
+
+    assert_eq!(trove.check_drift_command(true).unwrap().done, vec!["nvim"]);
+    let mut trove = Trove::load(None, tmp.home()).unwrap();
+    assert!(trove.check_drift_command(false).unwrap().failed.is_empty());
+}
+
+#[test]
+fn deploy_skips_every_excluded_category() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for (rel, cats) in [
+        (".vimrc", vec!["editor"]),
+        (".ssh/config", vec!["secrets"]),
+        (".gitconfig", vec!["work", "git"]),
+        (".zshrc", vec![]),
+    ] {
+        let path = tmp.write(rel, "");
+        let cats: Vec<String> = cats.into_iter().map(String::from).collect();
+        trove
+            .add_command(
+                &path,
+                &AddOptions {
+                    categories: cats.clone(),
+                    ..AddOptions::default()
+                },
+            )
+            .unwrap();
+    }
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+
+    let mut opts = DeployOptions::default();
+    opts.exclude.categories = vec!["secrets".into(), "work".into()];
+    let report = trove.deploy_command(&None, &None, any, &opts).unwrap();
+    assert_eq!(report.done, ["vimrc", "zshrc"]);
+    assert!(!tmp.path(".ssh/config").exists());
+    assert!(!tmp.path(".gitconfig").exists());
+}
+
+#[test]
+fn deploy_entry_reports_each_outcome() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    for path in [&vimrc, &zshrc] {
+        trove.add_command(path, &AddOptions::default()).unwrap();
+    }
+    let vim = trove.find_entry_by_name("vimrc").unwrap();
+    let zsh = trove.find_entry_by_name("zshrc").unwrap();
+    let mut notes = Vec::new();
+    let deploy = |e: &Entry, notes: &mut Vec<String>| {
+        return trove.deploy_entry(e, &DeployOptions::default(), notes);
+    };
+
+    assert_eq!(
+        deploy(&vim, &mut notes).unwrap(),
+        DeployOutcome::AlreadyLinked("already linked")
+    );
+    assert_eq!(trove.pack_entry(&vim, &None).unwrap(), PackOutcome::Packed);
+    assert_eq!(
+        trove.pack_entry(&vim, &None).unwrap(),
+        PackOutcome::Skipped("not deployed")
+    );
+    assert_eq!(deploy(&vim, &mut notes).unwrap(), DeployOutcome::Created);
+    assert!(is_symlink(&vimrc));
+
+    // a real file in the way is a conflict, not an error
+    trove.pack_entry(&zsh, &None).unwrap();
+    tmp.write(".zshrc", "local edits");
+    assert_eq!(
+        deploy(&zsh, &mut notes).unwrap(),
+        DeployOutcome::Conflict(format!("{} already exists", zshrc.display()))
+    );
+    assert_eq!(std::fs::read_to_string(&zshrc).unwrap(), "local edits");
+
+    std::fs::remove_file(tmp.path("trove/store/zshrc")).unwrap();
+    assert_eq!(
+        deploy(&zsh, &mut notes).unwrap(),
+        DeployOutcome::Skipped("missing from store".into())
+    );
+    assert!(trove
+        .deploy_entry(
+            &zsh,
+            &DeployOptions {
+                strict: true,
+                ..DeployOptions::default()
+            },
+            &mut notes
+        )
+        .is_err());
+    assert!(notes.is_empty());
+}
+
+#[test]
+fn add_list_skips_comments_and_continues_past_failures() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    let gitconfig = tmp.write(".gitconfig", "[user]");
+    let list = format!(
+        "# from the old manager\n{}\n\n{}\tzsh\tshell, login\n{}\n{}\tgit\tvcs\textra\n  \n{}\t\tvcs\n",
+        vimrc.display(),
+        zshrc.display(),
+        tmp.path(".missing").display(),
+        gitconfig.display(),
+        gitconfig.display(),
+    );
+
+    let report = trove.add_list_command(
+        &list,
+        &AddOptions {
+            categories: vec!["work".into()],
+            ..AddOptions::default()
+        },
+    );
+    assert_eq!(report.done, vec!["vimrc", "zsh", "gitconfig"]);
+    let failed: Vec<&str> = report.failed.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(
+        failed,
+        vec![
+            format!("line 5 ({})", tmp.path(".missing").display()),
+            format!("line 6 ({})", gitconfig.display()),
+        ]
+    );
+    assert!(report.failed[1].1.contains("at most 3"));
+
+    assert_eq!(
+        trove.find_entry_by_name("zsh").unwrap().categories,
+        vec!["work", "shell", "login"]
+    );
+    assert_eq!(
+        trove.find_entry_by_name("gitconfig").unwrap().categories,
+        vec!["work", "vcs"]
+    );
+    assert!(is_symlink(&zshrc));
+}
+
+#[test]
+fn add_list_fails_lines_with_an_empty_path() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let cwd = std::env::current_dir().unwrap();
+
+    let report = trove.add_list_command(" \tworkdir\n\tother\tshell\n", &AddOptions::default());
+    assert!(report.done.is_empty());
+    let failed: Vec<&str> = report.failed.iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(failed, vec!["line 1 ( )", "line 2 ()"]);
+    assert!(report.failed[0].1.contains("path field is empty"));
+    assert!(trove.entries.is_empty());
+    assert!(!is_symlink(&cwd));
+}
+
+#[test]
+fn move_path_copies_when_rename_crosses_devices() {
+    let tmp = TempHome::new();
+    let secret = tmp.write("src/nvim/lua/secret.lua", "token");
+    tmp.write("src/nvim/init.lua", "require('secret')");
+    symlink::symlink_file("init.lua", tmp.path("src/nvim/alias.lua")).unwrap();
+    let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+    let times = std::fs::FileTimes::new().set_modified(old);
+    std::fs::File::options()
+        .write(true)
+        .open(&secret)
+        .unwrap()
+        .set_times(times)
+        .unwrap();
+    #[cfg(unix)]
+    apply_mode(&secret, Some(0o600)).unwrap();
+
+    let cross = |_: &PathBuf, _: &PathBuf| -> std::io::Result<()> {
+        return Err(std::io::ErrorKind::CrossesDevices.into());
+    };
+    let (from, to) = (tmp.path("src/nvim"), tmp.path("store-nvim"));
+    move_path_with(&from, &to, &cross).unwrap();
+
+    assert!(!from.exists());
+    let moved = to.join("lua/secret.lua");
+    assert_eq!(std::fs::read_to_string(&moved).unwrap(), "token");
+    assert_eq!(std::fs::metadata(&moved).unwrap().modified().unwrap(), old);
+    #[cfg(unix)]
+    assert_eq!(file_mode(&moved), Some(0o600));
+    assert_eq!(
+        std::fs::read_link(to.join("alias.lua")).unwrap(),
+        PathBuf::from("init.lua")
+    );
+
+    // other rename errors are passed on and nothing is copied
+    let denied = |_: &PathBuf, _: &PathBuf| -> std::io::Result<()> {
+        return Err(std::io::ErrorKind::PermissionDenied.into());
+    };
+    let back = tmp.path("src/nvim");
+    assert!(move_path_with(&to, &back, &denied).is_err());
+    assert!(!back.exists());
+    assert!(to.exists());
+}
+
+#[test]
+fn run_report_lists_each_entry_outcome() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    for rel in [".vimrc", ".zshrc", ".bashrc"] {
+        let path = tmp.write(rel, "");
+        trove.add_command(&path, &AddOptions::default()).unwrap();
+    }
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    std::fs::remove_file(tmp.path("trove/store/bashrc")).unwrap();
+    tmp.write(".zshrc", "in the way");
+
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    let file = tmp.path("reports/deploy.json");
+    std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+    RunReport::new("deploy", &report)
+        .unwrap()
+        .write(&file)
+        .unwrap();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(json["action"], "deploy");
+    assert!(json["timestamp"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(
+        json["entries"],
+        serde_json::json!([
+            {"name": "bashrc", "outcome": "skipped", "reason": "missing from store"},
+            {"name": "vimrc", "outcome": "created"},
+            {
+                "name": "zshrc",
+                "outcome": "failed",
+                "reason": format!("{} already exists", tmp.path(".zshrc").display())
+            },
+        ])
+    );
+
+    let report = trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    let run = RunReport::new("pack", &report).unwrap();
+    assert_eq!(run.entries[1].outcome, "packed");
+}
+
+#[test]
+fn post_link_runs_after_linking_and_only_fails_when_strict() {
+    let tmp = TempHome::new();
+    let mut trove = tmp.init();
+    let vimrc = tmp.write(".vimrc", "set nu");
+    let zshrc = tmp.write(".zshrc", "export A=1");
+    for path in [&vimrc, &zshrc] {
+        trove.add_command(path, &AddOptions::default()).unwrap();
+    }
+    let ran = tmp.path("ran");
+    let cmd = format!(
+        "printf '%s %s' \"$TROVE_ENTRY\" \"$TROVE_HOST_PATH\" > '{}'",
+        ran.display()
+    );
+    trove.post_link_command("vimrc", Some(cmd)).unwrap();
+    trove
+        .post_link_command("zshrc", Some("echo nope >&2; exit 3".into()))
+        .unwrap();
+    let any = CategoryMatch::Any;
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert_eq!(report.done, ["vimrc", "zshrc"]);
+    assert_eq!(
+        std::fs::read_to_string(&ran).unwrap(),
+        format!("vimrc {}", vimrc.display())
+    );
+    assert_eq!(report.notes.len(), 1);
+    assert!(report.notes[0].starts_with("post_link for zshrc failed"));
+    assert!(report.notes[0].ends_with("nope"));
+    assert!(is_symlink(&zshrc));
+
+    // already linked entries don't run it again
+    std::fs::remove_file(&ran).unwrap();
+    trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert!(!ran.exists());
+
+    trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    let report = trove
+        .deploy_command(
+            &None,
+            &None,
+            any,
+            &DeployOptions {
+                strict: true,
+                ..DeployOptions::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.done, ["vimrc"]);
+    assert_eq!(report.failed[0].0, "zshrc");
+
+    trove.post_link_command("zshrc", None).unwrap();
+    assert_eq!(
+        Trove::load(None, tmp.home())
+            .unwrap()
+            .find_entry_by_name("zshrc")
+            .unwrap()
+            .post_link,
+        None
+    );
+}
+
+#[test]
+fn find_conf_upward_takes_the_nearest_trove_conf() {
+    let tmp = TempHome::new();
+    tmp.init();
+    let nested = tmp.path("trove/store/nvim/lua");
+    std::fs::create_dir_all(&nested).unwrap();
+    assert_eq!(
+        find_conf_upward(&nested),
+        Some(tmp.path("trove/trove.conf"))
+    );
+
+    // a closer one wins, and a directory named trove.conf doesn't count
+    std::fs::create_dir_all(tmp.path("trove/store/nvim/trove.conf")).unwrap();
+    assert_eq!(
+        find_conf_upward(&nested),
+        Some(tmp.path("trove/trove.conf"))
+    );
+    let project = tmp.write("trove/store/nvim/lua/trove.conf", "{}");
+    assert_eq!(find_conf_upward(&nested), Some(project));
+    assert_eq!(find_conf_upward(&tmp.path("elsewhere")), None);
+}
+
+#[test]
+fn store_inside_a_linked_home_round_trips() {
+    // the default store lives under $HOME, here reached through a symlinked home
+    let tmp = TempHome::new();
+    std::fs::create_dir_all(tmp.path("real/trove")).unwrap();
+    std::os::unix::fs::symlink(tmp.path("real"), tmp.path("home")).unwrap();
+    let mut trove = Trove::create(tmp.path("home/trove"), Home::at(tmp.path("home"))).unwrap();
+    assert!(trove.create_conf_symlink().unwrap());
+    let vimrc = tmp.write("real/.vimrc", "set nu");
+    // right next to the store, but not in it
+    let notes = tmp.write("real/trove/notes", "todo");
+    let any = CategoryMatch::Any;
+
+    for (path, host) in [
+        (".vimrc", "$HOME/.vimrc"),
+        ("trove/notes", "$HOME/trove/notes"),
+    ] {
+        let entry = trove
+            .add_command(&tmp.path("home").join(path), &AddOptions::default())
+            .unwrap();
+        assert_eq!(entry.host_path, host);
+    }
+    assert_eq!(
+        std::fs::read_link(&notes).unwrap(),
+        tmp.path("home/trove/store/notes")
+    );
+
+    let report = trove
+        .pack_command(&None, &None, any, &PackOptions::default())
+        .unwrap();
+    assert_eq!(report.done.len(), 2);
+    assert!(!vimrc.exists() && !notes.exists());
+    let report = trove
+        .deploy_command(&None, &None, any, &DeployOptions::default())
+        .unwrap();
+    assert_eq!(report.done.len(), 2);
+    assert_eq!(std::fs::read_to_string(&notes).unwrap(), "todo");
+    assert!(trove.verify_command(false).unwrap().failed.is_empty());
+
+    let report = trove
+        .remove_command(&Some(tmp.path("home/.vimrc")), &None, &None)
+        .unwrap();
+    assert_eq!(report.done, vec!["vimrc".to_string()]);
+    assert!(!is_symlink(&vimrc));
+    assert_eq!(std::fs::read_to_string(&vimrc).unwrap(), "set nu");
+    assert!(!tmp.path("real/trove/store/vimrc").exists());
+}